@@ -0,0 +1,79 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use nes_emu::cpu::{Cpu, ExecutionMode, FlatMemory};
+use nes_emu::traits::Memory;
+
+const ORIGIN: u16 = 0x8000;
+const INSTRUCTIONS_PER_ITERATION: u64 = 100_000;
+
+/// Endless loop mixing loads, stores, arithmetic, read-modify-writes and
+/// branches over most addressing modes
+fn create_memory() -> FlatMemory {
+    let program = asm_6502::compile(
+        r#"start:
+    LDX #0
+loop:
+    LDA $0200,X
+    ADC #3
+    STA $0200,X
+    INC $10
+    LDY $10
+    LDA ($20),Y
+    EOR $11
+    STA $11
+    ASL $12
+    ROL $13
+    INX
+    BNE loop
+    JMP start"#
+            .to_string(),
+        ORIGIN,
+    );
+    let mut memory = FlatMemory::new();
+    memory.load(&program, ORIGIN);
+    memory.mem_write_u16(0xFFFC, ORIGIN);
+    memory.mem_write_u16(0x20, 0x0300);
+    memory
+}
+
+/// Throughput in millions of instructions per second, from
+/// `cargo bench --bench cpu` run back to back on the same machine with the
+/// decode table and with the `HashMap` lookup it replaced :
+///
+/// | benchmark             | `HashMap` | table |
+/// |-----------------------|-----------|-------|
+/// | `cpu/step`            | 29.1      | 44.5  |
+/// | `cpu/step_cycle_mode` | 31.1      | 36.1  |
+///
+/// The `HashMap` figures come from this bench with `instruction::decode`
+/// replaced by :
+///
+/// ```ignore
+/// pub fn decode(opcode: Opcode) -> Option<&'static Instruction> {
+///     static MAP: OnceLock<HashMap<u8, &'static Instruction>> = OnceLock::new();
+///     MAP.get_or_init(|| INSTRUCTIONS.iter().map(|i| (i.opcode, i)).collect())
+///         .get(&opcode)
+///         .copied()
+/// }
+/// ```
+fn bench_step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(INSTRUCTIONS_PER_ITERATION));
+    for (name, execution_mode) in [
+        ("step", ExecutionMode::Instruction),
+        ("step_cycle_mode", ExecutionMode::Cycle),
+    ] {
+        let mut cpu = Cpu::with_execution_mode(create_memory(), execution_mode);
+        cpu.reset();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS_PER_ITERATION {
+                    cpu.step();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_step);
+criterion_main!(benches);
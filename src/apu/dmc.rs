@@ -0,0 +1,149 @@
+/// Output periods in CPU cycles
+//https://www.nesdev.org/wiki/APU_DMC
+pub const NTSC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+pub const PAL_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+const SAMPLES_START: u16 = 0xC000;
+const MAX_OUTPUT_LEVEL: u8 = 127;
+
+/// Delta modulation channel : plays 1-bit deltas read from memory by DMA.
+#[derive(Clone)]
+pub struct Dmc {
+    rates: &'static [u16; 16],
+    rate: u16,
+    timer: u16,
+    is_irq_enabled: bool,
+    is_looping: bool,
+    pub(super) irq: bool,
+    pub(super) output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    pub(super) bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    is_silenced: bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Self {
+            rates: &NTSC_RATES,
+            rate: NTSC_RATES[0],
+            timer: 0,
+            is_irq_enabled: false,
+            is_looping: false,
+            irq: false,
+            output_level: 0,
+            sample_address: SAMPLES_START,
+            sample_length: 1,
+            current_address: SAMPLES_START,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            is_silenced: true,
+        }
+    }
+
+    pub fn set_rates(&mut self, rates: &'static [u16; 16]) {
+        self.rates = rates;
+    }
+
+    /// Writes the register `register` of the channel, from 0 to 3.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.is_irq_enabled = data & 0b1000_0000 != 0;
+                if !self.is_irq_enabled {
+                    self.irq = false;
+                }
+                self.is_looping = data & 0b0100_0000 != 0;
+                self.rate = self.rates[usize::from(data & 0b1111)];
+            }
+            1 => self.output_level = data & MAX_OUTPUT_LEVEL,
+            2 => self.sample_address = SAMPLES_START | u16::from(data) << 6,
+            _ => self.sample_length = u16::from(data) << 4 | 1,
+        }
+    }
+
+    /// Enabling restarts the sample only once the previous one ended.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Address of the next sample byte, once the buffer is empty. The bus
+    /// fetches it with a DMA that halts the CPU.
+    pub fn dma_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    pub fn fill_sample_buffer(&mut self, byte: u8) {
+        self.sample_buffer = Some(byte);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.is_looping {
+                self.restart();
+            } else if self.is_irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.rate - 1;
+            self.clock_output();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_output(&mut self) {
+        if !self.is_silenced {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= MAX_OUTPUT_LEVEL - 2 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.shift_register = byte;
+                    self.is_silenced = false;
+                }
+                None => self.is_silenced = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
@@ -0,0 +1,290 @@
+mod dmc;
+mod noise;
+mod pulse;
+mod triangle;
+mod units;
+
+use crate::cartridge::Timing;
+use dmc::Dmc;
+use noise::Noise;
+use pulse::{Pulse, PulseChannel};
+use triangle::Triangle;
+
+pub const APU_REGISTERS_START: u16 = 0x4000;
+pub const APU_STATUS: u16 = 0x4015;
+pub const FRAME_COUNTER: u16 = 0x4017;
+/// $4015 reads don't drive D5
+pub const STATUS_DRIVEN_BITS: u8 = 0b1101_1111;
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// CPU cycles at which the frame counter clocks the channels, the 4-step
+/// sequence stops after the 4th one
+//https://www.nesdev.org/wiki/APU_Frame_Counter
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+/// Audio processing unit : 2 pulse channels, a triangle, a noise and a DMC,
+/// mixed into a stream of samples between 0.0 and 1.0.
+//https://www.nesdev.org/wiki/APU
+#[derive(Clone)]
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_steps: &'static [u32; 5],
+    is_five_step_mode: bool,
+    is_irq_inhibited: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    /// CPU cycles until a $4017 write resets the frame counter
+    frame_reset_delay: Option<u8>,
+    cycle: u64,
+    cpu_clock_rate: u32,
+    sample_rate: u32,
+    sample_clock: u32,
+    sample_sum: f32,
+    sample_count: u32,
+    samples: Vec<f32>,
+}
+
+impl Apu {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(PulseChannel::One),
+            pulse2: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_steps: &NTSC_FRAME_STEPS,
+            is_five_step_mode: false,
+            is_irq_inhibited: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            frame_reset_delay: None,
+            cycle: 0,
+            cpu_clock_rate: Timing::Ntsc.cpu_clock_rate(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_clock: 0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        let is_pal = timing == Timing::Pal;
+        self.frame_steps = if is_pal {
+            &PAL_FRAME_STEPS
+        } else {
+            &NTSC_FRAME_STEPS
+        };
+        self.noise.set_periods(if is_pal {
+            &noise::PAL_PERIODS
+        } else {
+            &noise::NTSC_PERIODS
+        });
+        self.dmc.set_rates(if is_pal {
+            &dmc::PAL_RATES
+        } else {
+            &dmc::NTSC_RATES
+        });
+        self.cpu_clock_rate = timing.cpu_clock_rate();
+    }
+
+    /// Samples per second produced from now on, 44100 by default.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0;
+        self.samples.clear();
+    }
+
+    /// Samples produced since the last call. Only the last second is kept
+    /// when they aren't taken.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Silences the channels, like the reset button does.
+    pub fn reset(&mut self) {
+        self.write_register(APU_STATUS, 0);
+        self.frame_irq = false;
+        self.frame_reset_delay = Some(3);
+    }
+
+    /// State of the /IRQ output, from the frame counter or the DMC.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    /// Address of the sample byte the DMC waits for, the bus reads it and
+    /// hands it to `fill_sample_buffer`.
+    pub fn dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    pub fn fill_sample_buffer(&mut self, byte: u8) {
+        self.dmc.fill_sample_buffer(byte);
+    }
+
+    //https://www.nesdev.org/wiki/APU_registers
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        let register = addr % 4;
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(register, data),
+            0x4004..=0x4007 => self.pulse2.write(register, data),
+            0x4008..=0x400B => self.triangle.write(register, data),
+            0x400C..=0x400F => self.noise.write(register, data),
+            0x4010..=0x4013 => self.dmc.write(register, data),
+            APU_STATUS => {
+                self.pulse1.length_counter.set_enabled(data & 0b0001 != 0);
+                self.pulse2.length_counter.set_enabled(data & 0b0010 != 0);
+                self.triangle.length_counter.set_enabled(data & 0b0100 != 0);
+                self.noise.length_counter.set_enabled(data & 0b1000 != 0);
+                self.dmc.set_enabled(data & 0b1_0000 != 0);
+            }
+            FRAME_COUNTER => {
+                self.is_five_step_mode = data & 0b1000_0000 != 0;
+                self.is_irq_inhibited = data & 0b0100_0000 != 0;
+                if self.is_irq_inhibited {
+                    self.frame_irq = false;
+                }
+                // the reset waits for the next APU cycle
+                self.frame_reset_delay = Some(if self.cycle % 2 == 1 { 4 } else { 3 });
+            }
+            _ => {}
+        }
+    }
+
+    /// Reading acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    /// `IF-D NT21` : DMC and frame IRQs, DMC active, length counters active
+    pub fn peek_status(&self) -> u8 {
+        u8::from(self.pulse1.length_counter.is_active())
+            | u8::from(self.pulse2.length_counter.is_active()) << 1
+            | u8::from(self.triangle.length_counter.is_active()) << 2
+            | u8::from(self.noise.length_counter.is_active()) << 3
+            | u8::from(self.dmc.bytes_remaining > 0) << 4
+            | u8::from(self.frame_irq) << 6
+            | u8::from(self.dmc.irq) << 7
+    }
+
+    /// Runs for one CPU cycle.
+    pub fn tick(&mut self) {
+        self.clock_frame_counter();
+        if self.cycle % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        self.cycle += 1;
+        self.sample();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        if let Some(delay) = self.frame_reset_delay {
+            if delay > 1 {
+                self.frame_reset_delay = Some(delay - 1);
+            } else {
+                self.frame_reset_delay = None;
+                self.frame_cycle = 0;
+                if self.is_five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+        }
+        self.frame_cycle += 1;
+        let steps = self.frame_steps;
+        let last_step = if self.is_five_step_mode {
+            steps[4]
+        } else {
+            steps[3]
+        };
+        match self.frame_cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => self.clock_quarter_frame(),
+            cycle if cycle == steps[1] || cycle == last_step => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if cycle == last_step {
+                    self.frame_irq |= !self.is_five_step_mode && !self.is_irq_inhibited;
+                    self.frame_cycle = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Averages the output of the cycles between two samples.
+    fn sample(&mut self) {
+        self.sample_sum += self.output();
+        self.sample_count += 1;
+        self.sample_clock += self.sample_rate;
+        if self.sample_clock < self.cpu_clock_rate {
+            return;
+        }
+        self.sample_clock -= self.cpu_clock_rate;
+        if self.samples.len() < self.sample_rate as usize {
+            self.samples
+                .push(self.sample_sum / self.sample_count as f32);
+        }
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+    }
+
+    /// The current output of the mixer.
+    pub fn output(&self) -> f32 {
+        mix(
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+}
+
+/// The non-linear mixer of the console, from the 4-bit outputs of the
+/// pulses, triangle and noise and the 7-bit output of the DMC.
+//https://www.nesdev.org/wiki/APU_Mixer
+fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse = f32::from(pulse1) + f32::from(pulse2);
+    let pulse_out = if pulse == 0.0 {
+        0.0
+    } else {
+        95.88 / (8128.0 / pulse + 100.0)
+    };
+    let tnd = f32::from(triangle) / 8227.0 + f32::from(noise) / 12241.0 + f32::from(dmc) / 22638.0;
+    let tnd_out = if tnd == 0.0 {
+        0.0
+    } else {
+        159.79 / (1.0 / tnd + 100.0)
+    };
+    pulse_out + tnd_out
+}
+
+#[cfg(test)]
+mod test;
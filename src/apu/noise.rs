@@ -0,0 +1,90 @@
+use super::units::{Envelope, LengthCounter};
+
+/// Timer periods in CPU cycles
+//https://www.nesdev.org/wiki/APU_Noise
+pub const NTSC_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+#[derive(Clone)]
+pub struct Noise {
+    periods: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    /// Feedback from bit 6 instead of bit 1, for a short sequence
+    is_short_mode: bool,
+    pub(super) shift_register: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            periods: &NTSC_PERIODS,
+            timer_period: NTSC_PERIODS[0],
+            timer: 0,
+            is_short_mode: false,
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    pub fn set_periods(&mut self, periods: &'static [u16; 16]) {
+        self.periods = periods;
+    }
+
+    /// Writes the register `register` of the channel, from 0 to 3.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length_counter.set_halted(data & 0b0010_0000 != 0);
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.is_short_mode = data & 0b1000_0000 != 0;
+                self.timer_period = self.periods[usize::from(data & 0b1111)];
+            }
+            _ => {
+                self.length_counter.load(data >> 3);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            self.clock_shift_register();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_shift_register(&mut self) {
+        let tap = if self.is_short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ self.shift_register >> tap) & 1;
+        self.shift_register = self.shift_register >> 1 | feedback << 14;
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 1 || !self.length_counter.is_active() {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
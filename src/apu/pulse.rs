@@ -0,0 +1,141 @@
+use super::units::{Envelope, LengthCounter};
+
+//https://www.nesdev.org/wiki/APU_Pulse
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+const MIN_PERIOD: u16 = 8;
+const MAX_PERIOD: u16 = 0x07FF;
+
+/// Which pulse channel, their sweep units negate differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PulseChannel {
+    /// Negates with the ones' complement
+    One,
+    /// Negates with the two's complement
+    Two,
+}
+
+#[derive(Clone)]
+pub struct Pulse {
+    channel: PulseChannel,
+    duty: usize,
+    step: usize,
+    pub(super) timer_period: u16,
+    timer: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length_counter: LengthCounter,
+    sweep: Sweep,
+}
+
+//https://www.nesdev.org/wiki/APU_Sweep
+#[derive(Clone, Default)]
+struct Sweep {
+    is_enabled: bool,
+    period: u8,
+    is_negated: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+impl Pulse {
+    pub fn new(channel: PulseChannel) -> Self {
+        Self {
+            channel,
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+            sweep: Sweep::default(),
+        }
+    }
+
+    /// Writes the register `register` of the channel, from 0 to 3.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = usize::from(data >> 6);
+                self.length_counter.set_halted(data & 0b0010_0000 != 0);
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep = Sweep {
+                    is_enabled: data & 0b1000_0000 != 0,
+                    period: (data >> 4) & 0b111,
+                    is_negated: data & 0b0000_1000 != 0,
+                    shift: data & 0b111,
+                    divider: self.sweep.divider,
+                    reload: true,
+                };
+            }
+            2 => self.timer_period = self.timer_period & 0xFF00 | u16::from(data),
+            _ => {
+                self.timer_period = self.timer_period & 0x00FF | u16::from(data & 0b111) << 8;
+                self.length_counter.load(data >> 3);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every other CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        let sweep = &self.sweep;
+        if sweep.divider == 0 && sweep.is_enabled && sweep.shift > 0 && !self.is_muted() {
+            self.timer_period = self.target_period();
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// Period the sweep unit moves to, computed continuously.
+    pub(super) fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if !self.sweep.is_negated {
+            return self.timer_period + change;
+        }
+        match self.channel {
+            PulseChannel::One => self.timer_period.saturating_sub(change + 1),
+            PulseChannel::Two => self.timer_period.saturating_sub(change),
+        }
+    }
+
+    /// The sweep unit mutes the channel even when it's disabled.
+    fn is_muted(&self) -> bool {
+        self.timer_period < MIN_PERIOD || self.target_period() > MAX_PERIOD
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.is_muted()
+            || !self.length_counter.is_active()
+            || DUTY_SEQUENCES[self.duty][self.step] == 0
+        {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
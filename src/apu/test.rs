@@ -0,0 +1,285 @@
+use super::*;
+
+fn tick(apu: &mut Apu, cycles: u32) {
+    for _ in 0..cycles {
+        apu.tick();
+    }
+}
+
+#[test]
+fn test_status_length_counters() {
+    let mut apu = Apu::new();
+    // disabled channels ignore the length loads
+    apu.write_register(0x4003, 0b0000_1000);
+    assert_eq!(0, apu.peek_status());
+    apu.write_register(APU_STATUS, 0b0000_1111);
+    apu.write_register(0x4003, 0b0000_1000);
+    apu.write_register(0x4007, 0b0000_1000);
+    apu.write_register(0x400B, 0b0000_1000);
+    apu.write_register(0x400F, 0b0000_1000);
+    assert_eq!(0b0000_1111, apu.peek_status());
+    apu.write_register(APU_STATUS, 0b0000_0101);
+    assert_eq!(0b0000_0101, apu.peek_status());
+}
+
+#[test]
+fn test_length_counter_counts_half_frames() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_0001);
+    // index 3 loads a length of 2
+    apu.write_register(0x4003, 3 << 3);
+    tick(&mut apu, NTSC_FRAME_STEPS[1]);
+    assert_eq!(1, apu.peek_status() & 1);
+    tick(&mut apu, NTSC_FRAME_STEPS[3] - NTSC_FRAME_STEPS[1]);
+    assert_eq!(0, apu.peek_status() & 1);
+}
+
+#[test]
+fn test_length_counter_halt() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_0001);
+    apu.write_register(0x4000, 0b0010_0000);
+    apu.write_register(0x4003, 3 << 3);
+    tick(&mut apu, 2 * NTSC_FRAME_STEPS[3]);
+    assert_eq!(1, apu.peek_status() & 1);
+}
+
+#[test]
+fn test_frame_irq_in_4_step_mode() {
+    let mut apu = Apu::new();
+    tick(&mut apu, NTSC_FRAME_STEPS[3] - 1);
+    assert!(!apu.irq());
+    tick(&mut apu, 1);
+    assert!(apu.irq());
+    assert_eq!(0x40, apu.peek_status());
+    assert_eq!(0x40, apu.read_status());
+    assert!(!apu.irq());
+    assert_eq!(0, apu.read_status());
+}
+
+#[test]
+fn test_no_frame_irq_in_5_step_mode_or_inhibited() {
+    let mut apu = Apu::new();
+    apu.write_register(FRAME_COUNTER, 0b1000_0000);
+    tick(&mut apu, 2 * NTSC_FRAME_STEPS[4]);
+    assert!(!apu.irq());
+
+    let mut apu = Apu::new();
+    tick(&mut apu, NTSC_FRAME_STEPS[3]);
+    apu.write_register(FRAME_COUNTER, 0b0100_0000);
+    assert!(!apu.irq());
+    tick(&mut apu, 2 * NTSC_FRAME_STEPS[3]);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_5_step_mode_write_clocks_half_frame() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_0001);
+    // length of 2
+    apu.write_register(0x4003, 3 << 3);
+    apu.write_register(FRAME_COUNTER, 0b1000_0000);
+    apu.write_register(FRAME_COUNTER, 0b1000_0000);
+    tick(&mut apu, 4);
+    assert_eq!(1, apu.peek_status() & 1);
+    apu.write_register(FRAME_COUNTER, 0b1000_0000);
+    tick(&mut apu, 4);
+    assert_eq!(0, apu.peek_status() & 1);
+}
+
+#[test]
+fn test_sweep_target_period() {
+    let mut pulse1 = Pulse::new(PulseChannel::One);
+    let mut pulse2 = Pulse::new(PulseChannel::Two);
+    for pulse in [&mut pulse1, &mut pulse2] {
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01);
+        // shift of 1
+        pulse.write(1, 0b1000_0001);
+        assert_eq!(0x180, pulse.target_period());
+        // negated
+        pulse.write(1, 0b1000_1001);
+    }
+    assert_eq!(0x7F, pulse1.target_period());
+    assert_eq!(0x80, pulse2.target_period());
+}
+
+#[test]
+fn test_sweep_mutes_pulse() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_0001);
+    // constant volume 15, 50% duty
+    apu.write_register(0x4000, 0b1001_1111);
+    apu.write_register(0x4002, 0x00);
+    apu.write_register(0x4003, 0x02);
+    let outputs = |apu: &mut Apu| {
+        (0..0x800 * 16)
+            .map(|_| {
+                apu.tick();
+                apu.pulse1.output()
+            })
+            .max()
+    };
+    assert_eq!(Some(15), outputs(&mut apu));
+    // the target period overflows even with the sweep disabled
+    apu.write_register(0x4002, 0xFF);
+    apu.write_register(0x4003, 0x07);
+    apu.write_register(0x4001, 0b0000_0001);
+    assert_eq!(Some(0), outputs(&mut apu));
+    // too high a frequency
+    apu.write_register(0x4002, 0x07);
+    apu.write_register(0x4003, 0x00);
+    assert_eq!(Some(0), outputs(&mut apu));
+}
+
+#[test]
+fn test_sweep_moves_period() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_0001);
+    apu.write_register(0x4002, 0x00);
+    apu.write_register(0x4003, 0x01);
+    // enabled, period 0, shift 1
+    apu.write_register(0x4001, 0b1000_0001);
+    tick(&mut apu, NTSC_FRAME_STEPS[1]);
+    assert_eq!(0x180, apu.pulse1.timer_period);
+}
+
+#[test]
+fn test_envelope_decays() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_1000);
+    // divider period 0
+    apu.write_register(0x400C, 0b0000_0000);
+    apu.write_register(0x400F, 0b0000_1000);
+    tick(&mut apu, NTSC_FRAME_STEPS[0]);
+    assert_eq!(15, apu.noise.envelope.output());
+    tick(&mut apu, NTSC_FRAME_STEPS[1] - NTSC_FRAME_STEPS[0]);
+    assert_eq!(14, apu.noise.envelope.output());
+}
+
+#[test]
+fn test_triangle_linear_counter() {
+    let mut apu = Apu::new();
+    apu.write_register(APU_STATUS, 0b0000_0100);
+    apu.write_register(0x4008, 2);
+    apu.write_register(0x400B, 0b0000_1000);
+    tick(&mut apu, NTSC_FRAME_STEPS[0]);
+    assert_eq!(2, apu.triangle.linear_counter);
+    tick(&mut apu, NTSC_FRAME_STEPS[2] - NTSC_FRAME_STEPS[0]);
+    assert_eq!(0, apu.triangle.linear_counter);
+    // the control flag keeps reloading it
+    apu.write_register(0x4008, 0b1000_0010);
+    apu.write_register(0x400B, 0b0000_1000);
+    tick(&mut apu, 2 * NTSC_FRAME_STEPS[3]);
+    assert_eq!(2, apu.triangle.linear_counter);
+}
+
+#[test]
+fn test_noise_sequence_lengths() {
+    let period = |mode: u8| {
+        let mut noise = Noise::new();
+        noise.write(2, mode);
+        let mut steps = 0;
+        loop {
+            noise.clock_shift_register();
+            steps += 1;
+            if noise.shift_register == 1 {
+                return steps;
+            }
+        }
+    };
+    assert_eq!(32767, period(0));
+    assert_eq!(93, period(0b1000_0000));
+}
+
+#[test]
+fn test_dmc_fetches_sample() {
+    let mut apu = Apu::new();
+    assert_eq!(None, apu.dma_address());
+    // IRQ enabled, $C040, 17 bytes
+    apu.write_register(0x4010, 0b1000_1111);
+    apu.write_register(0x4012, 0x01);
+    apu.write_register(0x4013, 0x01);
+    apu.write_register(APU_STATUS, 0b0001_0000);
+    assert_eq!(0b0001_0000, apu.peek_status());
+    for offset in 0..17 {
+        assert_eq!(Some(0xC040 + offset), apu.dma_address());
+        apu.fill_sample_buffer(0xFF);
+        assert_eq!(None, apu.dma_address());
+        tick(&mut apu, 8 * 54);
+    }
+    assert_eq!(0b1000_0000, apu.peek_status());
+    assert!(apu.irq());
+    // writing $4015 acknowledges it
+    apu.write_register(APU_STATUS, 0);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_dmc_loops() {
+    let mut apu = Apu::new();
+    apu.write_register(0x4010, 0b1100_0000);
+    apu.write_register(0x4012, 0xFF);
+    apu.write_register(APU_STATUS, 0b0001_0000);
+    assert_eq!(Some(0xFFC0), apu.dma_address());
+    apu.fill_sample_buffer(0);
+    tick(&mut apu, 8 * 428);
+    assert_eq!(Some(0xFFC0), apu.dma_address());
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_dmc_address_wraps() {
+    let mut dmc = Dmc::new();
+    dmc.write(2, 0xFF);
+    dmc.write(3, 0xFF);
+    dmc.set_enabled(true);
+    for _ in 0..0x3F {
+        dmc.fill_sample_buffer(0);
+        while dmc.dma_address().is_none() {
+            dmc.clock_timer();
+        }
+    }
+    assert_eq!(Some(0xFFFF), dmc.dma_address());
+    dmc.fill_sample_buffer(0);
+    while dmc.dma_address().is_none() {
+        dmc.clock_timer();
+    }
+    assert_eq!(Some(0x8000), dmc.dma_address());
+}
+
+#[test]
+fn test_dmc_output_level() {
+    let mut apu = Apu::new();
+    apu.write_register(0x4011, 0xFF);
+    assert_eq!(127, apu.dmc.output());
+    apu.write_register(0x4011, 0x40);
+    apu.write_register(0x4010, 0x0F);
+    apu.write_register(APU_STATUS, 0b0001_0000);
+    apu.fill_sample_buffer(0b0000_0011);
+    // the first byte goes to the shift register after the silent one
+    tick(&mut apu, 8 * 54 + 3 * 54);
+    assert_eq!(0x40 + 2 + 2 - 2, apu.dmc.output());
+}
+
+#[test]
+fn test_mixer() {
+    assert_eq!(0.0, mix(0, 0, 0, 0, 0));
+    assert!((mix(15, 15, 0, 0, 0) - 0.2585).abs() < 0.0001);
+    assert!((mix(0, 0, 15, 15, 127) - 0.7415).abs() < 0.0001);
+    assert!(mix(15, 15, 15, 15, 127) < 1.0);
+}
+
+#[test]
+fn test_sample_rate() {
+    let mut apu = Apu::new();
+    tick(&mut apu, Timing::Ntsc.cpu_clock_rate());
+    assert_eq!(44_100, apu.take_samples().len());
+    assert!(apu.take_samples().is_empty());
+    apu.set_sample_rate(48_000);
+    tick(&mut apu, Timing::Ntsc.cpu_clock_rate());
+    assert_eq!(48_000, apu.take_samples().len());
+    // only a second is kept
+    tick(&mut apu, 2 * Timing::Ntsc.cpu_clock_rate());
+    assert_eq!(48_000, apu.take_samples().len());
+}
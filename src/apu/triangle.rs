@@ -0,0 +1,74 @@
+use super::units::LengthCounter;
+
+//https://www.nesdev.org/wiki/APU_Triangle
+#[rustfmt::skip]
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+#[derive(Clone, Default)]
+pub struct Triangle {
+    timer_period: u16,
+    timer: u16,
+    step: usize,
+    pub(super) length_counter: LengthCounter,
+    /// Also halts the length counter
+    control: bool,
+    pub(super) linear_counter: u8,
+    linear_counter_period: u8,
+    linear_counter_reload: bool,
+}
+
+impl Triangle {
+    /// Writes the register `register` of the channel, from 0 to 3.
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.control = data & 0b1000_0000 != 0;
+                self.length_counter.set_halted(self.control);
+                self.linear_counter_period = data & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.timer_period = self.timer_period & 0xFF00 | u16::from(data),
+            _ => {
+                self.timer_period = self.timer_period & 0x00FF | u16::from(data & 0b111) << 8;
+                self.length_counter.load(data >> 3);
+                self.linear_counter_reload = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, the sequencer only moves while both counters
+    /// are non-zero.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length_counter.is_active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) % SEQUENCE.len();
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// A silenced triangle holds its last step instead of going to 0.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step]
+    }
+}
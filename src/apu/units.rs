@@ -0,0 +1,98 @@
+//! Units shared by several channels.
+
+//https://www.nesdev.org/wiki/APU_Length_Counter
+#[rustfmt::skip]
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Volume that decays at each quarter frame, or a constant volume
+//https://www.nesdev.org/wiki/APU_Envelope
+#[derive(Clone, Default)]
+pub struct Envelope {
+    start: bool,
+    is_looping: bool,
+    is_constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// `--LC VVVV` : loop, constant volume, volume or divider period
+    pub fn write(&mut self, data: u8) {
+        self.is_looping = data & 0b0010_0000 != 0;
+        self.is_constant = data & 0b0001_0000 != 0;
+        self.volume = data & 0b0000_1111;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.is_looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.is_constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+/// Silences its channel once it counted down, at each half frame
+#[derive(Clone, Default)]
+pub struct LengthCounter {
+    is_enabled: bool,
+    is_halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// Disabling the channel through $4015 clears the counter.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.is_enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halted(&mut self, halted: bool) {
+        self.is_halted = halted;
+    }
+
+    /// Loads the length at `index` in the table, from the 5 upper bits of
+    /// the last register of a channel.
+    pub fn load(&mut self, index: u8) {
+        if self.is_enabled {
+            self.counter = LENGTH_TABLE[usize::from(index & 0b1_1111)];
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.counter > 0 && !self.is_halted {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
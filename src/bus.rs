@@ -1,90 +1,343 @@
+use crate::apu::{self, Apu};
+use crate::cartridge::Timing;
+use crate::mapper::{Mapper, SharedMapper};
+use crate::memory_map::{MemoryMap, Region};
+use crate::ppu::Ppu;
 use crate::traits::{Device, Memory};
 
-use std::ops::Range;
-use std::ptr;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-const RAM_MIRRORING_MASK: u16 = 0b0000_0111_1111_1111;
-const PPU_REGISTERS_MIRRORING_MASK: u16 = 0b0010_0000_0000_0111;
+/// CPU cycles the CPU is halted for while the DMC fetches a sample byte
+//https://www.nesdev.org/wiki/APU_DMC#Memory_reader
+const DMC_DMA_CYCLES: u32 = 4;
+/// Writing a page number copies the page into OAM
+//https://www.nesdev.org/wiki/PPU_registers#OAMDMA
+const OAM_DMA: u16 = 0x4014;
+const OAM_DATA: u16 = 0x2004;
+/// $4016 writes go to the devices on both controller ports
+const CONTROLLER_PORTS: [u16; 2] = [0x4016, 0x4017];
 
+/// Owns everything wired to the CPU bus : the RAM, the PPU, the APU, the
+/// other devices and the cartridge, laid out by a memory map. Reads where nothing
+/// drives the data bus return its last value, like on hardware.
+//https://www.nesdev.org/wiki/Open_bus_behavior
 pub struct Bus {
-    memory: *mut [u8; 0xFFFF],
-    devices: Vec<(Range<usize>, *mut dyn Device)>,
+    memory_map: MemoryMap,
+    /// Backs the RAM regions, at their first mirror
+    memory: Box<[u8; 0x10000]>,
+    ppu: Ppu,
+    apu: Apu,
+    devices: Vec<Box<dyn Device>>,
+    mapper: Option<SharedMapper>,
+    ppu_clock_ratio: (u32, u32),
+    ppu_dots_remainder: u32,
+    /// Last value read or written by the CPU, returned by open bus reads
+    data_bus: u8,
+    /// CPU cycles stolen by the DMAs since the CPU last took them
+    stall_cycles: u32,
+    /// CPU cycles the devices ran for, DMAs start on even ones
+    cycle: u64,
 }
 
 impl Bus {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    pub fn with_memory_map(memory_map: MemoryMap) -> Self {
         Self {
-            memory: ptr::null_mut(),
+            memory_map,
+            memory: Box::new([0; 0x10000]),
+            ppu: Ppu::new(),
+            apu: Apu::new(),
             devices: Vec::new(),
+            mapper: None,
+            ppu_clock_ratio: Timing::Ntsc.ppu_clock_ratio(),
+            ppu_dots_remainder: 0,
+            data_bus: 0,
+            stall_cycles: 0,
+            cycle: 0,
         }
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn map(&mut self, memory: &mut [u8; 0xFFFF], devices: *const [*mut dyn Device]) {
-        self.memory = memory;
-        for device in (*devices).iter() {
-            let mapping = (**device).mapping_def();
-            (**device).map(&mut (*self.memory)[mapping.clone()]);
-            self.devices.push((mapping, *device));
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.ppu.set_timing(timing);
+        self.apu.set_timing(timing);
+        self.ppu_clock_ratio = timing.ppu_clock_ratio();
+    }
+
+    /// Runs the PPU and the APU for `cycles` CPU cycles. The sample bytes
+    /// the DMC fetches meanwhile steal cycles from the CPU, see
+    /// `Memory::take_stall_cycles`.
+    pub fn tick_devices(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.clock();
+            if let Some(addr) = self.apu.dma_address() {
+                let byte = self.mem_read_u8(addr);
+                self.apu.fill_sample_buffer(byte);
+                for _ in 0..DMC_DMA_CYCLES {
+                    self.clock();
+                }
+                self.stall_cycles += DMC_DMA_CYCLES;
+            }
+        }
+    }
+
+    fn clock(&mut self) {
+        self.cycle += 1;
+        let (numerator, denominator) = self.ppu_clock_ratio;
+        let dots = numerator + self.ppu_dots_remainder;
+        self.ppu_dots_remainder = dots % denominator;
+        self.ppu.tick(dots / denominator);
+        self.apu.tick();
+    }
+
+    /// Halts the CPU to copy `page` to OAM through $2004, a read and a
+    /// write per byte after one cycle to halt and one more to align on a
+    /// read cycle. The devices keep running meanwhile.
+    fn oam_dma(&mut self, page: u8) {
+        let halt_cycles = if self.cycle % 2 == 1 { 2 } else { 1 };
+        self.tick_devices(halt_cycles);
+        for offset in 0..=u8::MAX {
+            let byte = self.mem_read_u8(u16::from_le_bytes([offset, page]));
+            self.tick_devices(1);
+            self.ppu.write_register(OAM_DATA, byte);
+            self.tick_devices(1);
         }
+        self.stall_cycles += halt_cycles + 2 * 256;
+    }
+
+    /// The cartridge is shared with the PPU, which reads CHR through it.
+    pub fn insert_mapper(&mut self, mapper: Box<dyn Mapper>) {
+        let mapper: SharedMapper = Rc::new(RefCell::new(mapper));
+        self.ppu.connect(mapper.clone());
+        self.mapper = Some(mapper);
+    }
+
+    /// Maps `device` over its range of the I/O regions, after the PPU
+    /// registers.
+    pub fn add_device(&mut self, device: Box<dyn Device>) {
+        self.devices.push(device);
     }
 
-    fn mapped_device(&self, addr: u16) -> Option<*mut dyn Device> {
+    /// Reads the value answered at `addr`, the data bus keeps its last value
+    /// where nothing answers.
+    fn read(&mut self, addr: u16) -> u8 {
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow_mut().observe_cpu_read(addr);
+        }
+        let Some((region, mirrored)) = self.decode(addr) else {
+            return self.data_bus;
+        };
+        match region {
+            Region::Ram => self.memory[usize::from(mirrored)],
+            Region::ApuIo if mirrored == apu::APU_STATUS => {
+                let status = self.apu.read_status();
+                self.partially_driven(status, apu::STATUS_DRIVEN_BITS)
+            }
+            _ if region.is_cartridge() => match &self.mapper {
+                Some(mapper) if mapper.borrow().is_mapped(mirrored) => {
+                    mapper.borrow_mut().cpu_read(mirrored)
+                }
+                _ => self.data_bus,
+            },
+            _ => match self.device_mut(mirrored) {
+                Some(device) => {
+                    let driven_bits = device.driven_bits(mirrored);
+                    let value = device.read(mirrored);
+                    self.partially_driven(value, driven_bits)
+                }
+                None => self.data_bus,
+            },
+        }
+    }
+
+    /// The bits a device doesn't drive keep the last value of the data bus.
+    fn partially_driven(&self, value: u8, driven_bits: u8) -> u8 {
+        value & driven_bits | self.data_bus & !driven_bits
+    }
+
+    fn decode(&self, addr: u16) -> Option<(Region, u16)> {
+        self.memory_map
+            .decode(addr)
+            .map(|(mapping, mirrored)| (mapping.region, mirrored))
+    }
+
+    fn device(&self, addr: u16) -> Option<&dyn Device> {
+        let addr = usize::from(addr);
+        if self.ppu.mapping_def().contains(&addr) {
+            return Some(&self.ppu);
+        }
         self.devices
             .iter()
-            .find(|(range, _)| range.contains(&usize::from(addr)))
-            .map(|(_, device)| *device)
+            .find(|device| device.mapping_def().contains(&addr))
+            .map(|device| device.as_ref())
+    }
+
+    fn device_mut(&mut self, addr: u16) -> Option<&mut (dyn Device + 'static)> {
+        let addr = usize::from(addr);
+        if self.ppu.mapping_def().contains(&addr) {
+            return Some(&mut self.ppu);
+        }
+        self.devices
+            .iter_mut()
+            .find(|device| device.mapping_def().contains(&addr))
+            .map(|device| device.as_mut())
+    }
+
+    /// The strobe is latched by the controllers on both ports.
+    fn write_controller_strobe(&mut self, data: u8) {
+        for device in &mut self.devices {
+            let mapping = device.mapping_def();
+            if let Some(port) = CONTROLLER_PORTS
+                .into_iter()
+                .find(|&port| mapping.contains(&usize::from(port)))
+            {
+                device.write(port, data);
+            }
+        }
+    }
+}
+
+/// The copy gets its own cartridge, shared by its own PPU.
+impl Clone for Bus {
+    fn clone(&self) -> Self {
+        let mut bus = Self {
+            memory_map: self.memory_map.clone(),
+            memory: self.memory.clone(),
+            ppu: self.ppu.clone(),
+            apu: self.apu.clone(),
+            devices: self.devices.clone(),
+            mapper: None,
+            ppu_clock_ratio: self.ppu_clock_ratio,
+            ppu_dots_remainder: self.ppu_dots_remainder,
+            data_bus: self.data_bus,
+            stall_cycles: self.stall_cycles,
+            cycle: self.cycle,
+        };
+        if let Some(mapper) = &self.mapper {
+            bus.insert_mapper(mapper.borrow().clone_box());
+        }
+        bus
     }
 }
 
 impl Memory for Bus {
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn load(&mut self, data: &[u8], dest: u16) {
-        (*self.memory)[usize::from(dest)..usize::from(dest) + data.len()].copy_from_slice(data);
+    fn load(&mut self, data: &[u8], dest: u16) {
+        for (offset, &byte) in data.iter().enumerate() {
+            self.mem_write_u8(dest.wrapping_add(offset as u16), byte);
+        }
+    }
+
+    fn mem_read_u8(&mut self, addr: u16) -> u8 {
+        self.data_bus = self.read(addr);
+        self.data_bus
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read_u8(&mut self, addr: u16) -> u8 {
-        let addr = mirror_address(addr);
-        if let Some(device) = self.mapped_device(addr) {
-            (*device).mem_write();
+    fn mem_write_u8(&mut self, addr: u16, data: u8) {
+        self.data_bus = data;
+        let Some((region, mirrored)) = self.decode(addr) else {
+            return;
         };
-        (*self.memory)[usize::from(addr)]
+        match region {
+            Region::Ram => self.memory[usize::from(mirrored)] = data,
+            Region::ApuIo => match mirrored {
+                OAM_DMA => self.oam_dma(data),
+                0x4016 => self.write_controller_strobe(data),
+                0x4000..=0x4013 | apu::APU_STATUS | apu::FRAME_COUNTER => {
+                    self.apu.write_register(mirrored, data)
+                }
+                _ => {
+                    if let Some(device) = self.device_mut(mirrored) {
+                        device.write(mirrored, data)
+                    }
+                }
+            },
+            _ if region.is_cartridge() => {
+                if let Some(mapper) = &self.mapper {
+                    mapper.borrow_mut().cpu_write(mirrored, data)
+                }
+            }
+            _ => {
+                if let Some(device) = self.device_mut(mirrored) {
+                    device.write(mirrored, data)
+                }
+            }
+        }
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write_u8(&mut self, addr: u16, data: u8) {
-        let addr = mirror_address(addr);
-        (*self.memory)[usize::from(addr)] = data;
-        if let Some(device) = self.mapped_device(addr) {
-            (*device).mem_read();
+    /// Devices are peeked and the mapper doesn't see the access.
+    fn peek(&self, addr: u16) -> u8 {
+        let Some((region, mirrored)) = self.decode(addr) else {
+            return self.data_bus;
         };
+        match region {
+            Region::Ram => self.memory[usize::from(mirrored)],
+            Region::ApuIo if mirrored == apu::APU_STATUS => {
+                self.partially_driven(self.apu.peek_status(), apu::STATUS_DRIVEN_BITS)
+            }
+            _ if region.is_cartridge() => match &self.mapper {
+                Some(mapper) if mapper.borrow().is_mapped(mirrored) => {
+                    mapper.borrow().peek(mirrored)
+                }
+                _ => self.data_bus,
+            },
+            _ => match self.device(mirrored) {
+                Some(device) => {
+                    self.partially_driven(device.peek(mirrored), device.driven_bits(mirrored))
+                }
+                None => self.data_bus,
+            },
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.apu.irq()
+            || self
+                .mapper
+                .as_ref()
+                .is_some_and(|mapper| mapper.borrow().irq())
+    }
+
+    fn nmi(&self) -> bool {
+        self.ppu.nmi()
     }
-}
 
-fn mirror_address(addr: u16) -> u16 {
-    match addr {
-        0x0000..=0x1FFF => addr & RAM_MIRRORING_MASK,
-        0x2000..=0x3FFF => addr & PPU_REGISTERS_MIRRORING_MASK,
-        _ => addr,
+    fn tick(&mut self) {
+        self.tick_devices(1);
+    }
+
+    fn take_stall_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    fn ppu_position(&self) -> (u16, u16) {
+        (self.ppu.scanline(), self.ppu.dot())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::ops::Range;
 
+    /// Two registers, the first one counts its reads
+    #[derive(Clone)]
     struct MockDevice {
         start: usize,
-        memory: [*mut u8; 2],
+        registers: [u8; 2],
     }
 
     impl MockDevice {
         fn new(start: usize) -> Self {
             Self {
                 start,
-                memory: [std::ptr::null_mut(); 2],
+                registers: [0; 2],
             }
         }
     }
@@ -94,72 +347,294 @@ mod tests {
             self.start..self.start + 2
         }
 
-        fn map(&mut self, memory: &mut [u8]) {
-            for (count, pointer) in self.memory.iter_mut().enumerate() {
-                *pointer = &mut memory[count];
+        fn read(&mut self, addr: u16) -> u8 {
+            let value = self.peek(addr);
+            if usize::from(addr) == self.start {
+                self.registers[0] += 1;
             }
+            value
         }
 
-        #[allow(clippy::missing_safety_doc)]
-        unsafe fn mem_read(&mut self) {
-            for (count, i) in (self.start..self.start + 2).into_iter().enumerate() {
-                (*self.memory[count]) = (i + 1) as u8;
-            }
+        fn write(&mut self, addr: u16, value: u8) {
+            self.registers[usize::from(addr) - self.start] = value;
         }
 
-        #[allow(clippy::missing_safety_doc)]
-        unsafe fn mem_write(&mut self) {
-            for (count, i) in (self.start..self.start + 2).into_iter().enumerate() {
-                let val = (2 * i + 1) as u8;
-                (*self.memory[count]) = val;
+        fn peek(&self, addr: u16) -> u8 {
+            self.registers[usize::from(addr) - self.start]
+        }
+    }
+
+    use crate::mapper::Mapper;
+
+    /// RAM over the whole cartridge space
+    #[derive(Clone)]
+    struct MockMapper {
+        memory: Box<[u8; 0x10000]>,
+    }
+
+    impl MockMapper {
+        fn new() -> Self {
+            Self {
+                memory: Box::new([0; 0x10000]),
             }
         }
     }
 
+    impl Mapper for MockMapper {
+        fn peek(&self, addr: u16) -> u8 {
+            self.memory[usize::from(addr)]
+        }
+
+        fn cpu_write(&mut self, addr: u16, data: u8) {
+            self.memory[usize::from(addr)] = data;
+        }
+
+        fn ppu_read(&mut self, _addr: u16) -> u8 {
+            0
+        }
+
+        fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+        fn mirroring(&self) -> crate::cartridge::Mirroring {
+            crate::cartridge::Mirroring::Horizontal
+        }
+    }
+
     #[test]
-    fn test_bus_mapping_read() {
-        let mut memory = [99; 0xFFFF];
-        let mut expected = [99; 0xFFFF];
-        expected[0] = 1;
-        expected[1] = 3;
-        expected[10] = 21;
-        expected[11] = 23;
-
-        for i in 0..=1u8 {
-            let mut device_1 = MockDevice::new(0);
-            let mut device_2 = MockDevice::new(10);
-            let mut bus = Bus::new();
-            unsafe {
-                memory[0] = 10;
-                bus.map(&mut memory, &[&mut device_1, &mut device_2]);
-                bus.mem_read_u8(i as u16);
-                bus.mem_read_u8((i + 10) as u16);
-            }
-            assert_eq!(expected, memory);
-            memory = [99; 0xFFFF];
+    fn test_bus_cartridge_space() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.insert_mapper(Box::new(MockMapper::new()));
+        bus.mem_write_u8(0x8000, 42);
+        bus.mem_write_u8(0xFFFF, 24);
+        bus.mem_write_u8(0x0800, 12);
+        assert_eq!(42, bus.mem_read_u8(0x8000));
+        assert_eq!(24, bus.mem_read_u8(0xFFFF));
+        assert_eq!(12, bus.memory[0x0000]);
+        assert_eq!(0, bus.memory[0x8000]);
+    }
+
+    #[test]
+    fn test_bus_device_read() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.add_device(Box::new(MockDevice::new(0x4018)));
+        bus.add_device(Box::new(MockDevice::new(0x401A)));
+        assert_eq!(0, bus.mem_read_u8(0x4018));
+        assert_eq!(1, bus.mem_read_u8(0x4018));
+        assert_eq!(0, bus.mem_read_u8(0x401A));
+        assert_eq!(0, bus.memory[0x4018]);
+    }
+
+    #[test]
+    fn test_bus_device_write() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.add_device(Box::new(MockDevice::new(0x4018)));
+        bus.mem_write_u8(0x4019, 42);
+        bus.mem_write_u8(0x401A, 24);
+        assert_eq!(42, bus.mem_read_u8(0x4019));
+        assert_eq!(0, bus.memory[0x4019]);
+        assert_eq!(0, bus.memory[0x401A]);
+    }
+
+    #[test]
+    fn test_bus_peek_has_no_side_effects() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.add_device(Box::new(MockDevice::new(0x4018)));
+        for _ in 0..3 {
+            assert_eq!(0, bus.peek(0x4018));
         }
+        assert_eq!(0, bus.mem_read_u8(0x4018));
+        assert_eq!(1, bus.peek(0x4018));
     }
 
     #[test]
-    fn test_bus_mapping_write() {
-        let mut memory = [99; 0xFFFF];
-        let mut expected = [99; 0xFFFF];
-        expected[5] = 6;
-        expected[6] = 7;
-        expected[15] = 16;
-        expected[16] = 17;
-
-        for i in 0..=1u8 {
-            let mut device_1 = MockDevice::new(5);
-            let mut device_2 = MockDevice::new(15);
-            let mut bus = Bus::new();
-            unsafe {
-                bus.map(&mut memory, &[&mut device_1, &mut device_2]);
-                bus.mem_write_u8((i + 5) as u16, 0);
-                bus.mem_write_u8((i + 15) as u16, 0);
-            }
-            assert_eq!(expected, memory);
-            memory = [99; 0xFFFF];
+    fn test_bus_every_address() {
+        let value = |addr: u16| (addr ^ addr >> 8) as u8;
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.insert_mapper(Box::new(MockMapper::new()));
+        bus.add_device(Box::new(MockDevice::new(0x4018)));
+        for addr in 0..=u16::MAX {
+            bus.mem_write_u8(addr, value(addr));
+        }
+        for addr in 0..=u16::MAX {
+            let expected = match addr {
+                // the last mirror written wins
+                0x0000..=0x1FFF => value(0x1800 | addr & 0x07FF),
+                0x2000..=0x3FFF => continue,
+                // written to the APU
+                0x4015 => continue,
+                0x4018..=0x4019 => value(addr),
+                // the expansion space isn't decoded by the cartridge
+                0x4000..=0x5FFF => bus.data_bus,
+                _ => value(addr),
+            };
+            assert_eq!(expected, bus.peek(addr), "${addr:04X}");
+            assert_eq!(expected, bus.mem_read_u8(addr), "${addr:04X}");
+        }
+        assert_eq!(
+            u16::from_le_bytes([value(0xFFFF), value(0x1800)]),
+            bus.mem_read_u16(0xFFFF)
+        );
+    }
+
+    #[test]
+    fn test_bus_custom_memory_map() {
+        use crate::memory_map::Mapping;
+
+        let memory_map = MemoryMap::new(vec![
+            Mapping::new(0x0000..=0x7FFF, Region::Ram).mirrored(0x1000),
+            Mapping::new(0x8000..=0xFFFF, Region::PrgRom),
+        ])
+        .unwrap();
+        let mut bus = Bus::with_memory_map(memory_map);
+        bus.mem_write_u8(0x7FFF, 42);
+        assert_eq!(42, bus.mem_read_u8(0x0FFF));
+        // no cartridge, the last value read stays on the bus
+        assert_eq!(42, bus.mem_read_u8(0x8000));
+    }
+
+    #[test]
+    fn test_bus_open_bus() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.mem_write_u8(0x0010, 0x34);
+        assert_eq!(0x34, bus.mem_read_u8(0x4018));
+        bus.mem_write_u8(0x0011, 0x56);
+        bus.mem_read_u8(0x0010);
+        assert_eq!(0x34, bus.peek(0x5000));
+        assert_eq!(0x34, bus.mem_read_u8(0x5000));
+        // expansion space isn't decoded by most cartridges
+        bus.insert_mapper(Box::new(MockMapper::new()));
+        bus.mem_read_u8(0x0011);
+        assert_eq!(0x56, bus.mem_read_u8(0x5000));
+    }
+
+    #[test]
+    fn test_bus_joypad_upper_bits_are_open_bus() {
+        use crate::cpu::Cpu;
+        use crate::joypad::Joypad;
+
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        let mut mapper = MockMapper::new();
+        for offset in 0..9 {
+            // LDA $4016
+            mapper.memory[0x8000 + offset * 3..0x8000 + offset * 3 + 3]
+                .copy_from_slice(&[0xAD, 0x16, 0x40]);
+        }
+        mapper.memory[0xFFFC..0xFFFE].copy_from_slice(&[0x00, 0x80]);
+        bus.insert_mapper(Box::new(mapper));
+        bus.add_device(Box::new(Joypad::new(0x4016)));
+        let mut cpu = Cpu::new(bus);
+        cpu.reset();
+        let mut values = Vec::new();
+        for _ in 0..9 {
+            cpu.step();
+            values.push(cpu.registers().a);
+        }
+        // no button pressed, then 1 once the 8 buttons were read
+        assert_eq!(
+            vec![0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x41],
+            values
+        );
+    }
+
+    #[test]
+    fn test_bus_controller_ports() {
+        use crate::joypad::Joypad;
+
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.add_device(Box::new(Joypad::new(0x4016)));
+        bus.add_device(Box::new(Joypad::new(0x4017)));
+        let read_bits = |bus: &mut Bus, addr| (0..9).map(|_| bus.mem_read_u8(addr) & 1).sum::<u8>();
+        assert_eq!(1, read_bits(&mut bus, 0x4016));
+        assert_eq!(1, read_bits(&mut bus, 0x4017));
+        // $4017 writes go to the frame counter
+        bus.mem_write_u8(0x4017, 0b0100_0001);
+        assert_eq!(1, bus.mem_read_u8(0x4017) & 1);
+        // $4016 strobes both controllers
+        bus.mem_write_u8(0x4016, 1);
+        bus.mem_write_u8(0x4016, 0);
+        assert_eq!(0, bus.mem_read_u8(0x4016) & 1);
+        assert_eq!(0, bus.mem_read_u8(0x4017) & 1);
+    }
+
+    #[test]
+    fn test_bus_apu_status() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.mem_write_u8(0x4015, 0b0000_0001);
+        bus.mem_write_u8(0x4003, 0b0000_1000);
+        bus.mem_write_u8(0x0010, 0b0010_0000);
+        bus.mem_read_u8(0x0010);
+        // D5 is open bus
+        assert_eq!(0b0010_0001, bus.peek(0x4015));
+        assert_eq!(0b0010_0001, bus.mem_read_u8(0x4015));
+        // the APU registers are write-only
+        assert_eq!(0b0010_0001, bus.mem_read_u8(0x4003));
+        assert_eq!(0, bus.memory[0x4003]);
+    }
+
+    #[test]
+    fn test_bus_dmc_dma_stalls_cpu() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        let mut mapper = MockMapper::new();
+        mapper.memory[0xC040] = 0x55;
+        mapper.memory[0xC041] = 0xAA;
+        bus.insert_mapper(Box::new(mapper));
+        // $C040, 17 bytes
+        bus.mem_write_u8(0x4012, 0x01);
+        bus.mem_write_u8(0x4013, 0x01);
+        bus.mem_write_u8(0x4015, 0b0001_0000);
+        bus.tick_devices(1);
+        assert_eq!(4, bus.take_stall_cycles());
+        assert_eq!(0, bus.take_stall_cycles());
+        assert_eq!(0x55, bus.data_bus);
+        // the next byte waits for the buffer to empty
+        bus.tick_devices(100);
+        assert_eq!(0, bus.take_stall_cycles());
+        bus.tick_devices(8 * 428);
+        assert_eq!(4, bus.take_stall_cycles());
+        assert_eq!(0xAA, bus.data_bus);
+    }
+
+    #[test]
+    fn test_bus_oam_dma() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        for offset in 0..=0xFF {
+            bus.mem_write_u8(0x0200 + offset, offset as u8 ^ 0x5A);
         }
+        bus.mem_write_u8(0x4014, 0x02);
+        assert_eq!(513, bus.take_stall_cycles());
+        for offset in [0, 1, 0x80, 0xFF] {
+            bus.mem_write_u8(0x2003, offset);
+            assert_eq!(offset ^ 0x5A, bus.peek(0x2004));
+        }
+        // one more cycle to align when starting on an odd cycle
+        bus.mem_write_u8(0x4014, 0x02);
+        assert_eq!(514, bus.take_stall_cycles());
+        // the PPU ran during the DMA
+        let (scanline, dot) = (bus.ppu().scanline(), bus.ppu().dot());
+        assert_eq!(3 * (513 + 514), u32::from(scanline) * 341 + u32::from(dot));
+    }
+
+    #[test]
+    fn test_bus_peek_ppu_status() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        // runs the PPU to VBlank
+        bus.tick_devices(82_182 / 3 + 1);
+        assert_eq!(0x80, bus.peek(0x2002) & 0x80);
+        assert_eq!(0x80, bus.peek(0x2002) & 0x80);
+        assert_eq!(0x80, bus.mem_read_u8(0x2002) & 0x80);
+        assert_eq!(0, bus.peek(0x2002) & 0x80);
+    }
+
+    #[test]
+    fn test_clone_owns_its_cartridge() {
+        let mut bus = Bus::with_memory_map(MemoryMap::nes());
+        bus.insert_mapper(Box::new(MockMapper::new()));
+        bus.mem_write_u8(0x8000, 1);
+        bus.mem_write_u8(0x0010, 1);
+        let mut copy = bus.clone();
+        copy.mem_write_u8(0x8000, 2);
+        copy.mem_write_u8(0x0010, 2);
+        assert_eq!((1, 1), (bus.mem_read_u8(0x8000), bus.mem_read_u8(0x0010)));
+        assert_eq!((2, 2), (copy.mem_read_u8(0x8000), copy.mem_read_u8(0x0010)));
     }
 }
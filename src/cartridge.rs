@@ -0,0 +1,471 @@
+use std::fmt;
+
+const NES_TAG: [u8; 4] = [b'N', b'E', b'S', 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const TRAINER_PRG_RAM_OFFSET: usize = 0x1000;
+pub const PRG_ROM_BANK_SIZE: usize = 0x4000;
+pub const CHR_ROM_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+pub const CHR_RAM_SIZE: usize = 0x2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    HeaderTooShort(usize),
+    InvalidTag([u8; 4]),
+    InvalidRomSize,
+    NoPrgRom,
+    Truncated { expected: usize, actual: usize },
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::HeaderTooShort(len) => {
+                write!(
+                    f,
+                    "file is {len} bytes long, an iNES header needs {HEADER_SIZE}"
+                )
+            }
+            Error::InvalidTag(tag) => write!(f, "invalid iNES tag {tag:02X?}"),
+            Error::InvalidRomSize => write!(f, "header declares ROM sizes too large to load"),
+            Error::NoPrgRom => write!(f, "header declares no PRG-ROM"),
+            Error::Truncated { expected, actual } => {
+                write!(f, "file is {actual} bytes long, header declares {expected}")
+            }
+            Error::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem { ppu_type: u8, hardware_type: u8 },
+    Playchoice10,
+    Extended(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+impl Timing {
+    pub fn cpu_clock_rate(&self) -> u32 {
+        match self {
+            Timing::Ntsc | Timing::MultiRegion => 1_789_773,
+            Timing::Pal => 1_662_607,
+            Timing::Dendy => 1_773_448,
+        }
+    }
+
+    /// PPU dots per CPU cycle, as a numerator and a denominator.
+    pub fn ppu_clock_ratio(&self) -> (u32, u32) {
+        match self {
+            Timing::Pal => (16, 5),
+            _ => (3, 1),
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Timing::Ntsc | Timing::MultiRegion => 262,
+            Timing::Pal | Timing::Dendy => 312,
+        }
+    }
+
+    /// The Dendy idles for 51 post-render scanlines instead of 1, its VBlank
+    /// lasts 20 scanlines like on NTSC.
+    //https://www.nesdev.org/wiki/Cycle_reference_chart
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Timing::Dendy => 291,
+            _ => 241,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub format: Format,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub timing: Timing,
+    pub console_type: ConsoleType,
+    pub default_expansion_device: u8,
+}
+
+impl Header {
+    //https://www.nesdev.org/wiki/INES
+    //https://www.nesdev.org/wiki/NES_2.0
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_SIZE {
+            return Err(Error::HeaderTooShort(data.len()));
+        }
+        let tag = [data[0], data[1], data[2], data[3]];
+        if tag != NES_TAG {
+            return Err(Error::InvalidTag(tag));
+        }
+
+        let header = if data[7] & 0b0000_1100 == 0b0000_1000 {
+            Self::parse_nes_2(data)
+        } else {
+            Self::parse_ines(data)
+        };
+        if header.prg_rom_size == 0 {
+            return Err(Error::NoPrgRom);
+        }
+        Ok(header)
+    }
+
+    fn parse_ines(data: &[u8]) -> Self {
+        let flags_6 = data[6];
+        // Old dumpers wrote their name ("DiskDude!") over bytes 7-15,
+        // in that case the upper mapper nibble, the PRG-RAM size and the TV
+        // system are garbage.
+        let [flags_7, byte_8, byte_9] = if data[12..HEADER_SIZE].iter().any(|&byte| byte != 0) {
+            [0; 3]
+        } else {
+            [data[7], data[8], data[9]]
+        };
+        let has_battery = flags_6 & 0b0000_0010 != 0;
+        let chr_rom_size = usize::from(data[5]) * CHR_ROM_BANK_SIZE;
+        let prg_ram_size = usize::from(byte_8.max(1)) * PRG_RAM_BANK_SIZE;
+
+        Self {
+            format: Format::INes,
+            mapper: u16::from(flags_7 & 0xF0 | flags_6 >> 4),
+            submapper: 0,
+            mirroring: parse_mirroring(flags_6),
+            has_battery,
+            has_trainer: flags_6 & 0b0000_0100 != 0,
+            prg_rom_size: usize::from(data[4]) * PRG_ROM_BANK_SIZE,
+            chr_rom_size,
+            prg_ram_size: if has_battery { 0 } else { prg_ram_size },
+            prg_nvram_size: if has_battery { prg_ram_size } else { 0 },
+            chr_ram_size: if chr_rom_size == 0 { CHR_RAM_SIZE } else { 0 },
+            chr_nvram_size: 0,
+            timing: if byte_9 & 1 == 1 {
+                Timing::Pal
+            } else {
+                Timing::Ntsc
+            },
+            console_type: parse_console_type(flags_7, 0),
+            default_expansion_device: 0,
+        }
+    }
+
+    fn parse_nes_2(data: &[u8]) -> Self {
+        let flags_6 = data[6];
+        let flags_7 = data[7];
+
+        Self {
+            format: Format::Nes2,
+            mapper: u16::from(data[8] & 0x0F) << 8 | u16::from(flags_7 & 0xF0 | flags_6 >> 4),
+            submapper: data[8] >> 4,
+            mirroring: parse_mirroring(flags_6),
+            has_battery: flags_6 & 0b0000_0010 != 0,
+            has_trainer: flags_6 & 0b0000_0100 != 0,
+            prg_rom_size: nes_2_rom_size(data[4], data[9] & 0x0F, PRG_ROM_BANK_SIZE),
+            chr_rom_size: nes_2_rom_size(data[5], data[9] >> 4, CHR_ROM_BANK_SIZE),
+            prg_ram_size: nes_2_ram_size(data[10] & 0x0F),
+            prg_nvram_size: nes_2_ram_size(data[10] >> 4),
+            chr_ram_size: nes_2_ram_size(data[11] & 0x0F),
+            chr_nvram_size: nes_2_ram_size(data[11] >> 4),
+            timing: match data[12] & 0b11 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultiRegion,
+                _ => Timing::Dendy,
+            },
+            console_type: parse_console_type(flags_7, data[13]),
+            default_expansion_device: data[15] & 0b0011_1111,
+        }
+    }
+}
+
+fn parse_mirroring(flags_6: u8) -> Mirroring {
+    if flags_6 & 0b0000_1000 != 0 {
+        Mirroring::FourScreen
+    } else if flags_6 & 0b0000_0001 != 0 {
+        Mirroring::Vertical
+    } else {
+        Mirroring::Horizontal
+    }
+}
+
+fn parse_console_type(flags_7: u8, byte_13: u8) -> ConsoleType {
+    match flags_7 & 0b11 {
+        0 => ConsoleType::Nes,
+        1 => ConsoleType::VsSystem {
+            ppu_type: byte_13 & 0x0F,
+            hardware_type: byte_13 >> 4,
+        },
+        2 => ConsoleType::Playchoice10,
+        _ => ConsoleType::Extended(byte_13 & 0x0F),
+    }
+}
+
+fn nes_2_rom_size(lsb: u8, msb: u8, bank_size: usize) -> usize {
+    if msb == 0x0F {
+        // exponent-multiplier notation : 2^E * (MM * 2 + 1)
+        let exponent = u32::from(lsb >> 2);
+        let multiplier = usize::from(lsb & 0b11) * 2 + 1;
+        2usize.saturating_pow(exponent).saturating_mul(multiplier)
+    } else {
+        (usize::from(msb) << 8 | usize::from(lsb)) * bank_size
+    }
+}
+
+fn nes_2_ram_size(shift_count: u8) -> usize {
+    if shift_count == 0 {
+        0
+    } else {
+        64 << shift_count
+    }
+}
+
+#[derive(Clone)]
+pub struct Cartridge {
+    pub header: Header,
+    pub trainer: Option<Vec<u8>>,
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub chr_ram: Vec<u8>,
+    pub prg_ram: Vec<u8>,
+}
+
+impl Cartridge {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let header = Header::parse(data)?;
+
+        let trainer_size = if header.has_trainer { TRAINER_SIZE } else { 0 };
+        let prg_start = HEADER_SIZE + trainer_size;
+        let chr_start = prg_start
+            .checked_add(header.prg_rom_size)
+            .ok_or(Error::InvalidRomSize)?;
+        let end = chr_start
+            .checked_add(header.chr_rom_size)
+            .ok_or(Error::InvalidRomSize)?;
+        if data.len() < end {
+            return Err(Error::Truncated {
+                expected: end,
+                actual: data.len(),
+            });
+        }
+
+        let trainer = header
+            .has_trainer
+            .then(|| data[HEADER_SIZE..prg_start].to_vec());
+        let mut prg_ram = vec![0; header.prg_ram_size + header.prg_nvram_size];
+        // The trainer is mapped at $7000, in the middle of PRG-RAM
+        if let (Some(trainer), Some(dest)) = (
+            &trainer,
+            prg_ram.get_mut(TRAINER_PRG_RAM_OFFSET..TRAINER_PRG_RAM_OFFSET + TRAINER_SIZE),
+        ) {
+            dest.copy_from_slice(trainer);
+        }
+
+        Ok(Self {
+            trainer,
+            prg_rom: data[prg_start..chr_start].to_vec(),
+            chr_rom: data[chr_start..end].to_vec(),
+            chr_ram: vec![0; header.chr_ram_size + header.chr_nvram_size],
+            prg_ram,
+            header,
+        })
+    }
+
+    pub fn has_chr_ram(&self) -> bool {
+        self.chr_rom.is_empty()
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        if self.has_chr_ram() {
+            &self.chr_ram
+        } else {
+            &self.chr_rom
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_rom(flags_6: u8, flags_7: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut rom = vec![
+            b'N', b'E', b'S', 0x1A, prg_banks, chr_banks, flags_6, flags_7,
+        ];
+        rom.resize(HEADER_SIZE, 0);
+        if flags_6 & 0b0000_0100 != 0 {
+            rom.extend([0x77; TRAINER_SIZE]);
+        }
+        rom.extend(vec![0xAA; usize::from(prg_banks) * PRG_ROM_BANK_SIZE]);
+        rom.extend(vec![0xCC; usize::from(chr_banks) * CHR_ROM_BANK_SIZE]);
+        rom
+    }
+
+    #[test]
+    fn test_parse_nrom() {
+        let cartridge = Cartridge::from_bytes(&create_rom(0b0000_0001, 0, 2, 1)).unwrap();
+        assert_eq!(0, cartridge.header.mapper);
+        assert_eq!(Mirroring::Vertical, cartridge.header.mirroring);
+        assert_eq!(2 * PRG_ROM_BANK_SIZE, cartridge.prg_rom.len());
+        assert_eq!(CHR_ROM_BANK_SIZE, cartridge.chr_rom.len());
+        assert!(cartridge.chr_ram.is_empty());
+        assert!(cartridge.trainer.is_none());
+        assert!(cartridge.prg_rom.iter().all(|&byte| byte == 0xAA));
+        assert!(cartridge.chr_rom.iter().all(|&byte| byte == 0xCC));
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let cartridge = Cartridge::from_bytes(&create_rom(0b0100_1110, 0b0001_0000, 1, 0)).unwrap();
+        assert_eq!(0x14, cartridge.header.mapper);
+        assert_eq!(Mirroring::FourScreen, cartridge.header.mirroring);
+        assert!(cartridge.header.has_battery);
+        assert_eq!(Some(vec![0x77; TRAINER_SIZE]), cartridge.trainer);
+        assert_eq!(
+            [0x77; TRAINER_SIZE],
+            cartridge.prg_ram[TRAINER_PRG_RAM_OFFSET..TRAINER_PRG_RAM_OFFSET + TRAINER_SIZE]
+        );
+        assert!(cartridge.prg_rom.iter().all(|&byte| byte == 0xAA));
+        assert_eq!(CHR_RAM_SIZE, cartridge.chr_ram.len());
+    }
+
+    #[test]
+    fn test_ignore_dirty_flags_7() {
+        let mut rom = create_rom(0b0001_0000, 0b0100_0000, 1, 1);
+        rom[12..16].copy_from_slice(b"ude!");
+        let cartridge = Cartridge::from_bytes(&rom).unwrap();
+        assert_eq!(1, cartridge.header.mapper);
+    }
+
+    #[test]
+    fn test_ignore_disk_dude_garbage() {
+        let mut rom = create_rom(0, 0, 1, 1);
+        rom[7..16].copy_from_slice(b"DiskDude!");
+        let cartridge = Cartridge::from_bytes(&rom).unwrap();
+        assert_eq!(0, cartridge.header.mapper);
+        assert_eq!(Timing::Ntsc, cartridge.header.timing);
+        assert_eq!(PRG_RAM_BANK_SIZE, cartridge.header.prg_ram_size);
+        assert_eq!(ConsoleType::Nes, cartridge.header.console_type);
+    }
+
+    #[test]
+    fn test_parse_nes_2() {
+        let mut rom = create_rom(0b0100_0011, 0b0000_1001, 2, 0);
+        rom[8] = 0x31;
+        rom[10] = 0x97;
+        rom[11] = 0x07;
+        rom[12] = 0x03;
+        rom[13] = 0x21;
+        rom[15] = 0x01;
+        let cartridge = Cartridge::from_bytes(&rom).unwrap();
+        let header = &cartridge.header;
+        assert_eq!(Format::Nes2, header.format);
+        assert_eq!(0x104, header.mapper);
+        assert_eq!(3, header.submapper);
+        assert_eq!(Mirroring::Vertical, header.mirroring);
+        assert_eq!(0x2000, header.prg_ram_size);
+        assert_eq!(0x8000, header.prg_nvram_size);
+        assert_eq!(0x2000, header.chr_ram_size);
+        assert_eq!(0, header.chr_nvram_size);
+        assert_eq!(Timing::Dendy, header.timing);
+        assert_eq!(
+            ConsoleType::VsSystem {
+                ppu_type: 1,
+                hardware_type: 2
+            },
+            header.console_type
+        );
+        assert_eq!(1, header.default_expansion_device);
+        assert_eq!(0xA000, cartridge.prg_ram.len());
+        assert_eq!(0x2000, cartridge.chr_ram.len());
+    }
+
+    #[test]
+    fn test_nes_2_rom_sizes() {
+        assert_eq!(0x1_0000, nes_2_rom_size(4, 0, PRG_ROM_BANK_SIZE));
+        assert_eq!(0x40_2000, nes_2_rom_size(1, 2, CHR_ROM_BANK_SIZE));
+        assert_eq!(
+            3 * 1024,
+            nes_2_rom_size(10 << 2 | 1, 0x0F, PRG_ROM_BANK_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_ines_battery_ram_is_non_volatile() {
+        let cartridge = Cartridge::from_bytes(&create_rom(0b0000_0010, 0, 1, 1)).unwrap();
+        assert_eq!(Format::INes, cartridge.header.format);
+        assert_eq!(0, cartridge.header.prg_ram_size);
+        assert_eq!(0x2000, cartridge.header.prg_nvram_size);
+        assert_eq!(Timing::Ntsc, cartridge.header.timing);
+    }
+
+    #[test]
+    fn test_malformed_roms() {
+        assert_eq!(
+            Some(Error::HeaderTooShort(4)),
+            Cartridge::from_bytes(b"NES\x1A").err()
+        );
+        let mut rom = create_rom(0, 0, 1, 1);
+        rom[3] = 0;
+        assert_eq!(
+            Some(Error::InvalidTag([b'N', b'E', b'S', 0])),
+            Cartridge::from_bytes(&rom).err()
+        );
+        assert_eq!(
+            Some(Error::NoPrgRom),
+            Cartridge::from_bytes(&create_rom(0, 0, 0, 1)).err()
+        );
+        let rom = create_rom(0, 0, 1, 1);
+        assert_eq!(
+            Some(Error::Truncated {
+                expected: rom.len(),
+                actual: rom.len() - 1
+            }),
+            Cartridge::from_bytes(&rom[..rom.len() - 1]).err()
+        );
+        // NES 2.0 exponent-multiplier sizes can't be added up
+        let mut rom = create_rom(0, 0b0000_1000, 1, 1);
+        rom[4] = 0xFF;
+        rom[9] = 0x0F;
+        assert_eq!(
+            Some(Error::InvalidRomSize),
+            Cartridge::from_bytes(&rom).err()
+        );
+    }
+}
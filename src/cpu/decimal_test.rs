@@ -0,0 +1,72 @@
+//! Runs Klaus Dormann's 6502 decimal test : ADC and SBC in decimal mode with
+//! every pair of operands, it clears ERROR once they all matched.
+//https://github.com/Klaus2m5/6502_65C02_functional_tests
+use super::{Cpu, FlatMemory, Variant};
+use crate::traits::Memory;
+use std::path::Path;
+
+/// The binary assembled with the default configuration starts at its origin
+const START: u16 = 0x0200;
+/// 1 while the test runs, 0 once it passed
+const ERROR_ADDR: u16 = 0x000B;
+/// `end_of_test` assembles to the 65C02 STP instruction, a NMOS 6502 would
+/// run it as DCP
+const STP: u8 = 0xDB;
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+fn run_decimal_test(program: &[u8]) -> Result<(), String> {
+    let mut memory = FlatMemory::new();
+    memory.load(program, START);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.set_variant(Variant::Nmos6502);
+    cpu.counter = START;
+    let mut instructions = 0;
+    while cpu.memory().memory[usize::from(cpu.counter)] != STP {
+        if instructions == MAX_INSTRUCTIONS {
+            return Err(format!(
+                "no STP reached after {MAX_INSTRUCTIONS} instructions"
+            ));
+        }
+        cpu.step();
+        instructions += 1;
+    }
+    match memory.memory[usize::from(ERROR_ADDR)] {
+        0 => Ok(()),
+        error => Err(format!("ERROR is ${error:02X}")),
+    }
+}
+
+#[test]
+fn test_reports_error_byte() {
+    #[rustfmt::skip]
+    let failing = [
+        0xA9, 0x01,         // LDA #$01
+        0x85, 0x0B,         // STA ERROR
+        STP,
+    ];
+    assert_eq!(Err("ERROR is $01".to_string()), run_decimal_test(&failing));
+    #[rustfmt::skip]
+    let passing = [
+        0xA9, 0x01,         // LDA #$01
+        0x85, 0x0B,         // STA ERROR
+        0xF8,               // SED
+        0x69, 0x09,         // ADC #$09
+        0xC9, 0x10,         // CMP #$10
+        0xD0, 0x02,         // BNE done
+        0xC6, 0x0B,         // DEC ERROR
+        STP,                // done
+    ];
+    assert_eq!(Ok(()), run_decimal_test(&passing));
+}
+
+/// The binary isn't distributed with the sources, put 6502_decimal_test.bin,
+/// assembled at $0200, in tests/roms and run `cargo test -- --ignored`.
+#[test]
+#[ignore = "needs tests/roms/6502_decimal_test.bin"]
+fn test_6502_decimal_test() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/6502_decimal_test.bin");
+    let program = std::fs::read(&path).unwrap_or_else(|err| panic!("no {path:?} : {err}"));
+    if let Err(report) = run_decimal_test(&program) {
+        panic!("{report}");
+    }
+}
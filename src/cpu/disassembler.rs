@@ -0,0 +1,140 @@
+use super::instruction::{decode, Mode, Name};
+use std::ops::Range;
+
+/// Disassembles the instruction at `addr` and returns its text and length.
+/// `peek` reads memory without side effects, like `Bus::peek`, for the
+/// opcode and the two bytes after it, wrapping around at $FFFF.
+pub fn disassemble(peek: impl Fn(u16) -> u8, addr: u16) -> (String, u8) {
+    let opcode = peek(addr);
+    let Some(instruction) = decode(opcode) else {
+        return (format!(".db ${opcode:02X}"), 1);
+    };
+    let byte = peek(addr.wrapping_add(1));
+    let word = u16::from_le_bytes([byte, peek(addr.wrapping_add(2))]);
+    let operand = match instruction.mode {
+        Mode::Implicit => String::new(),
+        Mode::Accumulator => " A".to_string(),
+        Mode::Immediate => format!(" #${byte:02X}"),
+        Mode::ZeroPage => format!(" ${byte:02X}"),
+        Mode::ZeroPageX => format!(" ${byte:02X},X"),
+        Mode::ZeroPageY => format!(" ${byte:02X},Y"),
+        Mode::Absolute => format!(" ${word:04X}"),
+        Mode::AbsoluteX => format!(" ${word:04X},X"),
+        Mode::AbsoluteY => format!(" ${word:04X},Y"),
+        Mode::Indirect => format!(" (${word:04X})"),
+        Mode::IndirectX => format!(" (${byte:02X},X)"),
+        Mode::IndirectY => format!(" (${byte:02X}),Y"),
+        Mode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!(" ${target:04X}")
+        }
+    };
+    (
+        format!("{}{operand}", mnemonic(&instruction.name)),
+        instruction.len,
+    )
+}
+
+/// Disassembles every instruction starting in `range`, with its address.
+pub fn disassemble_range(peek: impl Fn(u16) -> u8, range: Range<u16>) -> Vec<(u16, String)> {
+    let mut lines = Vec::new();
+    let mut addr = u32::from(range.start);
+    while addr < u32::from(range.end) {
+        let (text, len) = disassemble(&peek, addr as u16);
+        lines.push((addr as u16, text));
+        addr += u32::from(len);
+    }
+    lines
+}
+
+/// ISC goes by ISB in the nestest logs
+fn mnemonic(name: &Name) -> String {
+    match name {
+        Name::Isc => "ISB".to_string(),
+        _ => format!("{name:?}").to_uppercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads `bytes` from $0000, 0 past their end
+    fn peek(bytes: &[u8]) -> impl Fn(u16) -> u8 + '_ {
+        |addr| bytes.get(usize::from(addr)).copied().unwrap_or_default()
+    }
+
+    fn disassemble_text(bytes: &[u8]) -> String {
+        disassemble(peek(bytes), 0).0
+    }
+
+    #[test]
+    fn test_disassemble_modes() {
+        assert_eq!("NOP", disassemble_text(&[0xEA]));
+        assert_eq!("ASL A", disassemble_text(&[0x0A]));
+        assert_eq!("LDA #$20", disassemble_text(&[0xA9, 0x20]));
+        assert_eq!("STA $20", disassemble_text(&[0x85, 0x20]));
+        assert_eq!("LDY $20,X", disassemble_text(&[0xB4, 0x20]));
+        assert_eq!("LDX $20,Y", disassemble_text(&[0xB6, 0x20]));
+        assert_eq!("JMP $C5F5", disassemble_text(&[0x4C, 0xF5, 0xC5]));
+        assert_eq!("LDA $1234,X", disassemble_text(&[0xBD, 0x34, 0x12]));
+        assert_eq!("LDA $1234,Y", disassemble_text(&[0xB9, 0x34, 0x12]));
+        assert_eq!("JMP ($10FF)", disassemble_text(&[0x6C, 0xFF, 0x10]));
+        assert_eq!("LDA ($20,X)", disassemble_text(&[0xA1, 0x20]));
+        assert_eq!("LDA ($20),Y", disassemble_text(&[0xB1, 0x20]));
+        assert_eq!("LAX $20", disassemble_text(&[0xA7, 0x20]));
+        assert_eq!("ISB $20", disassemble_text(&[0xE7, 0x20]));
+    }
+
+    #[test]
+    fn test_disassemble_branch_target() {
+        let mut memory = vec![0; 0xC012];
+        memory[0xC000..0xC002].copy_from_slice(&[0xD0, 0x10]);
+        assert_eq!(
+            ("BNE $C012".to_string(), 2),
+            disassemble(peek(&memory), 0xC000)
+        );
+        memory[0xC000..0xC002].copy_from_slice(&[0xF0, 0xFC]);
+        assert_eq!(
+            ("BEQ $BFFE".to_string(), 2),
+            disassemble(peek(&memory), 0xC000)
+        );
+    }
+
+    #[test]
+    fn test_disassemble_wraps_around() {
+        let mut memory = vec![0; 0x10000];
+        memory[0xFFFF] = 0xAD;
+        memory[0x0000..0x0002].copy_from_slice(&[0x34, 0x12]);
+        assert_eq!(
+            ("LDA $1234".to_string(), 3),
+            disassemble(peek(&memory), 0xFFFF)
+        );
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        #[rustfmt::skip]
+        let memory = [
+            0xA9, 0x80,         // LDA #$80
+            0x8D, 0x00, 0x20,   // STA $2000
+            0x4C, 0x05, 0x00,   // JMP $0005
+        ];
+        assert_eq!(
+            vec![
+                (0x0000, "LDA #$80".to_string()),
+                (0x0002, "STA $2000".to_string()),
+                (0x0005, "JMP $0005".to_string()),
+            ],
+            disassemble_range(peek(&memory), 0x0000..0x0008)
+        );
+        assert_eq!(
+            vec![(0x0002, "STA $2000".to_string())],
+            disassemble_range(peek(&memory), 0x0002..0x0004)
+        );
+        assert_eq!(
+            vec![(0xFFFE, "BRK".to_string())],
+            disassemble_range(peek(&[]), 0xFFFE..0xFFFF)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use crate::traits::Memory;
+
+/// 64 KiB of RAM over the whole address space, to run bare 6502 programs
+/// such as test suites and benchmarks.
+#[derive(Clone)]
+pub struct FlatMemory {
+    pub memory: Box<[u8; 0x10000]>,
+}
+
+impl FlatMemory {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            memory: Box::new([0; 0x10000]),
+        }
+    }
+}
+
+impl Memory for FlatMemory {
+    fn load(&mut self, data: &[u8], dest: u16) {
+        self.memory[usize::from(dest)..usize::from(dest) + data.len()].copy_from_slice(data);
+    }
+
+    fn mem_read_u8(&mut self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
+    }
+
+    fn mem_write_u8(&mut self, addr: u16, byte: u8) {
+        self.memory[usize::from(addr)] = byte
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
+    }
+}
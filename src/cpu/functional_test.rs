@@ -0,0 +1,77 @@
+//! Runs Klaus Dormann's 6502 functional test : every test traps in an
+//! infinite loop on failure, the success trap is the only one at the end.
+//https://github.com/Klaus2m5/6502_65C02_functional_tests
+use super::{disassemble, Cpu, FlatMemory, Variant};
+use crate::traits::Memory;
+use std::path::Path;
+
+const START: u16 = 0x0400;
+/// Trap reached when every test passed, in the binary assembled with the
+/// default configuration
+const SUCCESS_TRAP: u16 = 0x3469;
+/// The test stores the number of the running test case here
+const TEST_CASE_ADDR: u16 = 0x0200;
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+/// Runs from `start` until an instruction jumps to itself and returns the
+/// address of that trap.
+fn run_until_trap(memory: &mut FlatMemory, start: u16) -> Result<u16, String> {
+    let mut cpu = Cpu::new(memory);
+    cpu.set_variant(Variant::Nmos6502);
+    cpu.counter = start;
+    for _ in 0..MAX_INSTRUCTIONS {
+        let counter = cpu.counter;
+        cpu.step();
+        if cpu.counter == counter && cpu.pending_interrupt.is_none() {
+            return Ok(counter);
+        }
+    }
+    Err(format!(
+        "no trap reached after {MAX_INSTRUCTIONS} instructions"
+    ))
+}
+
+fn run_functional_test(image: &[u8], success_trap: u16) -> Result<(), String> {
+    let mut memory = FlatMemory::new();
+    memory.load(image, 0);
+    let trap = run_until_trap(&mut memory, START)?;
+    if trap != success_trap {
+        let test_case = memory.memory[usize::from(TEST_CASE_ADDR)];
+        let (instruction, _) = disassemble(|addr| memory.memory[usize::from(addr)], trap);
+        return Err(format!(
+            "trapped at ${trap:04X} ({instruction}) in test case ${test_case:02X}"
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_reports_trap_and_test_case() {
+    #[rustfmt::skip]
+    let program = [
+        0xA9, 0x05,         // LDA #$05
+        0x8D, 0x00, 0x02,   // STA $0200
+        0xA9, 0x00,         // LDA #$00
+        0xD0, 0xFE,         // BNE *
+        0xF0, 0xFE,         // BEQ *
+    ];
+    let mut image = vec![0; usize::from(START)];
+    image.extend(program);
+    assert_eq!(
+        Err("trapped at $0409 (BEQ $0409) in test case $05".to_string()),
+        run_functional_test(&image, 0x0407)
+    );
+    assert_eq!(Ok(()), run_functional_test(&image, 0x0409));
+}
+
+/// The binary isn't distributed with the sources, put the 64K image of
+/// 6502_functional_test.bin in tests/roms and run `cargo test -- --ignored`.
+#[test]
+#[ignore = "needs tests/roms/6502_functional_test.bin"]
+fn test_6502_functional_test() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/6502_functional_test.bin");
+    let image = std::fs::read(&path).unwrap_or_else(|err| panic!("no {path:?} : {err}"));
+    if let Err(report) = run_functional_test(&image, SUCCESS_TRAP) {
+        panic!("{report}");
+    }
+}
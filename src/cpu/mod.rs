@@ -1,27 +1,104 @@
+mod disassembler;
+mod flat_memory;
 mod instruction;
 mod register;
 
-use instruction::INSTRUCTION_MAP;
+pub use disassembler::{disassemble, disassemble_range};
+pub use flat_memory::FlatMemory;
 
+use crate::trace::{TraceEntry, Tracer};
 use crate::traits::Memory;
 
+const NMI_VECTOR: u16 = 0xFFFA;
 const PROGRAM_POINTER: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
 const STACK_ADDR_HI: register::StackPointer = 0x01;
 pub const STACK_TOP: register::StackPointer = 0xFF;
 const IMPLICIT_MODE_ADDR: u16 = u16::MAX;
+const INTERRUPT_CYCLES: u64 = 7;
+/// Constant ORed with A by XAA and LXA, it varies between chips
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+/// What to do with the opcodes whose behaviour isn't reliable on hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnstableOpcodes {
+    Emulate,
+    /// Emulate and report them on stderr
+    Log,
+    /// Lock up the CPU like a JAM opcode
+    Halt,
+}
+
+/// How `Cpu::step` accesses the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// Each instruction runs at once and its cycles are counted from a table.
+    Instruction,
+    /// Each cycle makes one bus access, dummy ones included, and ticks the
+    /// memory first : other chips see the accesses at the right cycle.
+    Cycle,
+}
+
+/// Chip emulated by the core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The NES CPU, a 6502 whose decimal mode is disconnected
+    Ricoh2A03,
+    /// The original 6502, ADC and SBC honour the D flag
+    Nmos6502,
+}
+
+/// The copy of a CPU doesn't trace, a tracer can't be shared.
+#[derive(Default)]
+struct TracerSlot(Option<Tracer>);
+
+impl Clone for TracerSlot {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// Registers as seen between two instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub p: u8,
+}
 
-pub struct Cpu {
+#[derive(Clone)]
+pub struct Cpu<M: Memory> {
     counter: register::ProgramCounter,
     stack_pointer: register::StackPointer,
     a: register::A,
     x: register::X,
     y: register::Y,
     status: register::Status,
-    memory: *mut dyn Memory,
+    memory: M,
+    execution_mode: ExecutionMode,
+    nmi_input: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    irq_line: bool,
+    /// NMI and IRQ as seen before the current cycle, in cycle mode
+    sampled_interrupts: (bool, bool),
+    pending_interrupt: Option<u16>,
+    cycles: u64,
+    unstable_opcodes: UnstableOpcodes,
+    variant: Variant,
+    is_halted: bool,
+    tracer: TracerSlot,
 }
 
-impl Cpu {
-    pub fn new(memory: *mut dyn Memory) -> Self {
+impl<M: Memory> Cpu<M> {
+    pub fn new(memory: M) -> Self {
+        Self::with_execution_mode(memory, ExecutionMode::Instruction)
+    }
+
+    pub fn with_execution_mode(memory: M, execution_mode: ExecutionMode) -> Self {
         Self {
             counter: 0,
             stack_pointer: STACK_TOP,
@@ -30,143 +107,443 @@ impl Cpu {
             y: 0,
             status: register::Status::INITIAL_STATE,
             memory,
+            execution_mode,
+            nmi_input: false,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+            sampled_interrupts: (false, false),
+            pending_interrupt: None,
+            cycles: 0,
+            unstable_opcodes: UnstableOpcodes::Emulate,
+            variant: Variant::Ricoh2A03,
+            is_halted: false,
+            tracer: TracerSlot::default(),
+        }
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    pub fn set_unstable_opcodes(&mut self, behaviour: UnstableOpcodes) {
+        self.unstable_opcodes = behaviour;
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.execution_mode
+    }
+
+    /// A JAM opcode locks the CPU up until the next reset.
+    pub fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.counter,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.stack_pointer,
+            p: self.status.bits(),
+        }
+    }
+
+    /// Traces every instruction executed from now on, `None` stops tracing.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = TracerSlot(tracer);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.0.as_ref()
+    }
+
+    /// State before the instruction at PC is executed, the memory gives the
+    /// position of the PPU.
+    pub fn trace_entry(&self) -> TraceEntry {
+        let pc = self.counter;
+        let (scanline, dot) = self.memory.ppu_position();
+        TraceEntry {
+            registers: self.registers(),
+            bytes: [0, 1, 2].map(|offset| self.memory.peek(pc.wrapping_add(offset))),
+            cycles: self.cycles,
+            scanline,
+            dot,
+        }
+    }
+
+    fn trace(&mut self) {
+        let entry = self.trace_entry();
+        if let Some(tracer) = &mut self.tracer.0 {
+            tracer.record(&entry);
+        }
+    }
+
+    /// The next step runs an interrupt sequence instead of an instruction.
+    pub fn has_pending_interrupt(&self) -> bool {
+        self.pending_interrupt.is_some()
+    }
+
+    /// Cycles elapsed since power-up.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Counts `cycles` the CPU spent halted by a DMA.
+    pub fn stall(&mut self, cycles: u32) {
+        self.cycles += u64::from(cycles);
+    }
+
+    /// Drives the /NMI input, which is also asserted by the memory's `nmi()`
+    /// in cycle mode. An NMI is latched when the line gets asserted.
+    pub fn set_nmi(&mut self, asserted: bool) {
+        self.nmi_input = asserted;
+        self.detect_nmi_edge(asserted);
+    }
+
+    fn detect_nmi_edge(&mut self, asserted: bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = asserted;
+    }
+
+    /// Drives the /IRQ input, which is also asserted by the memory's `irq()`.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    /// Power-up is a reset sequence starting from a cleared stack pointer.
+    //https://www.nesdev.org/wiki/CPU_power_up_state
+    pub fn power_up(&mut self) {
+        self.stack_pointer = 0;
+        self.status = register::Status::INITIAL_STATE;
+        self.reset();
+    }
+
+    /// The reset sequence is an interrupt whose stack writes are turned into
+    /// reads : registers are kept, only SP, I and PC are updated.
+    //https://www.nesdev.org/wiki/CPU_power_up_state#After_reset
+    pub fn reset(&mut self) {
+        self.dummy_read(self.counter);
+        self.dummy_read(self.counter);
+        for _ in 0..3 {
+            self.read(self.get_stack_addr());
+            self.stack_pointer = self.stack_pointer.wrapping_sub(1);
         }
+        self.status.insert(register::Status::INTERRUPT_DISABLE);
+        self.nmi_pending = false;
+        self.pending_interrupt = None;
+        self.is_halted = false;
+        self.add_cycles(INTERRUPT_CYCLES);
+        self.counter = self.read_u16(PROGRAM_POINTER);
     }
 
-    #[allow(clippy::missing_safety_doc)]
+    /// Executes an instruction, or the interrupt sequence polled at the end
+    /// of the previous one, and returns the number of cycles it took.
     #[rustfmt::skip]
-    pub unsafe fn run(&mut self)
+    pub fn step(&mut self) -> u32
     {
-        self.counter = (*self.memory).mem_read_u16(PROGRAM_POINTER);
-        loop {
-            let instruct = INSTRUCTION_MAP.get(&(*self.memory).mem_read_u8(self.counter)).unwrap();
-            if instruct.opcode == 0 {
-                self.brk();
-                break
-            }
-            self.counter += 1;
-            let previous_position = self.counter;
-            let addr = self.get_operand_address(&instruct.mode);
-            let operand = if addr != IMPLICIT_MODE_ADDR {
-                (*self.memory).mem_read_u8(addr)
-            } else {
-                0
-            };
-            match instruct.name {
-                instruction::Name::Adc => self.adc(operand),//tested
-                instruction::Name::And => self.and(operand),//tested
-                instruction::Name::Asl if instruct.mode == instruction::Mode::Accumulator
-                                       => self.asl_a(),
-                instruction::Name::Asl => self.asl(operand, addr),
-                instruction::Name::Bit => self.bit(operand),
-                instruction::Name::Bcc => self.bcc(addr),//tested
-                instruction::Name::Bcs => self.bcs(addr),//tested
-                instruction::Name::Beq => self.beq(addr),//tested
-                instruction::Name::Bmi => self.bmi(addr),//tested
-                instruction::Name::Bne => self.bne(addr),//tested
-                instruction::Name::Bpl => self.bpl(addr),//tested
-                instruction::Name::Bvc => self.bvc(addr),//tested
-                instruction::Name::Bvs => self.bvs(addr),//tested
-                instruction::Name::Clc => self.clc(),//tested
-                instruction::Name::Cld => self.cld(),
-                instruction::Name::Cli => self.cli(),
-                instruction::Name::Clv => self.clv(),//tested
-                instruction::Name::Cmp => self.cmp(operand),//tested
-                instruction::Name::Cpx => self.cpx(operand),//tested 
-                instruction::Name::Cpy => self.cpy(operand),//tested
-                instruction::Name::Dec => self.dec(operand, addr),//tested
-                instruction::Name::Dex => self.dex(),//tested
-                instruction::Name::Dey => self.dey(),//tested
-                instruction::Name::Eor => self.eor(operand), //tested
-                instruction::Name::Inc => self.inc(operand, addr), //tested
-                instruction::Name::Inx => self.inx(),//tested
-                instruction::Name::Iny => self.iny(),//tested
-                instruction::Name::Jmp => self.jmp(addr),
-                instruction::Name::Jsr => self.jsr(addr),
-                instruction::Name::Lda => self.lda(operand),//tested
-                instruction::Name::Ldx => self.ldx(operand),//tested
-                instruction::Name::Ldy => self.ldy(operand),//tested
-                instruction::Name::Lsr if instruct.mode == instruction::Mode::Accumulator
-                                       => self.lsr_a(),//tested
-                instruction::Name::Lsr => self.lsr(operand, addr),//tested
-                instruction::Name::Nop => self.nop(),
-                instruction::Name::Ora => self.ora(operand),//tested
-                instruction::Name::Pha => self.pha(), //tested
-                instruction::Name::Php => self.php(),
-                instruction::Name::Pla => self.pla(), //tested
-                instruction::Name::Plp => self.plp(),
-                instruction::Name::Rol if instruct.mode == instruction::Mode::Accumulator
-                                       => self.rol_a(), //tested
-                instruction::Name::Rol => self.rol(operand, addr),//tested
-                instruction::Name::Ror if instruct.mode == instruction::Mode::Accumulator
-                                       => self.ror_a(),//tested
-                instruction::Name::Ror => self.ror(operand, addr),//tested
-                instruction::Name::Rti => self.rti(),
-                instruction::Name::Rts => self.rts(),
-                instruction::Name::Sbc => self.sbc(operand),
-                instruction::Name::Sec => self.sec(),//tested
-                instruction::Name::Sed => self.sed(),
-                instruction::Name::Sei => self.sei(),
-                instruction::Name::Sta => self.sta(addr),//tested
-                instruction::Name::Stx => self.stx(addr),//tested
-                instruction::Name::Sty => self.sty(addr),//testes,
-                instruction::Name::Tax => self.tax(),//tested
-                instruction::Name::Tay => self.tay(),//tested
-                instruction::Name::Tsx => self.tsx(),
-                instruction::Name::Txa => self.txa(),//tested
-                instruction::Name::Txs => self.txs(),
-                instruction::Name::Tya => self.tya(),//tested
-                _ => todo!()
+        if self.is_halted {
+            self.add_cycles(1);
+            self.cycle();
+            return 1;
+        }
+        let start_cycles = self.cycles;
+        if let Some(vector) = self.pending_interrupt.take() {
+            // the opcode fetch is discarded
+            self.dummy_read(self.counter);
+            self.dummy_read(self.counter);
+            self.interrupt(vector, false);
+            return (self.cycles - start_cycles) as u32;
+        }
+        if self.tracer.0.is_some() {
+            self.trace();
+        }
+        let opcode = self.read(self.counter);
+        let Some(instruct) = instruction::decode(opcode) else {
+            unreachable!("opcode {opcode:#04X} is missing from the decode table")
+        };
+        let interrupt_disable = self.status.is_set(register::Status::INTERRUPT_DISABLE);
+        if instruct.opcode == 0 {
+            self.brk();
+            return (self.cycles - start_cycles) as u32;
+        }
+        if instruct.is_unstable() && self.unstable_opcodes == UnstableOpcodes::Log {
+            eprintln!("unstable opcode {:#04X} at {:#06X}", instruct.opcode, self.counter);
+        }
+        let jams = instruct.name == instruction::Name::Jam
+            || instruct.is_unstable() && self.unstable_opcodes == UnstableOpcodes::Halt;
+        if jams {
+            self.is_halted = true;
+            for _ in 1..instruct.cycles {
+                self.dummy_read(self.counter.wrapping_add(1));
             }
-            if !self.has_branched(previous_position) {
-                self.counter += u16::from(instruct.len - 1);
+            self.add_cycles(u64::from(instruct.cycles));
+            return (self.cycles - start_cycles) as u32;
+        }
+        self.counter = self.counter.wrapping_add(1);
+        let previous_position = self.counter;
+        if matches!(instruct.mode, instruction::Mode::Implicit | instruction::Mode::Accumulator) {
+            self.dummy_read(self.counter);
+        }
+        let (addr, page_crossed) = self.get_operand_address(instruct);
+        let mut has_branched = instruct.loads_counter();
+        let operand = if instruct.reads_operand() {
+            self.read(addr)
+        } else {
+            0
+        };
+        if instruct.is_read_modify_write() {
+            self.write(addr, operand);
+        }
+        match instruct.name {
+            instruction::Name::Adc => self.adc(operand),//tested
+            instruction::Name::And => self.and(operand),//tested
+            instruction::Name::Asl if instruct.mode == instruction::Mode::Accumulator
+                                   => self.asl_a(),
+            instruction::Name::Asl => self.asl(operand, addr),
+            instruction::Name::Bit => self.bit(operand),
+            instruction::Name::Bcc => has_branched = self.bcc(addr),//tested
+            instruction::Name::Bcs => has_branched = self.bcs(addr),//tested
+            instruction::Name::Beq => has_branched = self.beq(addr),//tested
+            instruction::Name::Bmi => has_branched = self.bmi(addr),//tested
+            instruction::Name::Bne => has_branched = self.bne(addr),//tested
+            instruction::Name::Bpl => has_branched = self.bpl(addr),//tested
+            instruction::Name::Bvc => has_branched = self.bvc(addr),//tested
+            instruction::Name::Bvs => has_branched = self.bvs(addr),//tested
+            instruction::Name::Clc => self.clc(),//tested
+            instruction::Name::Cld => self.cld(),
+            instruction::Name::Cli => self.cli(),
+            instruction::Name::Clv => self.clv(),//tested
+            instruction::Name::Cmp => self.cmp(operand),//tested
+            instruction::Name::Cpx => self.cpx(operand),//tested 
+            instruction::Name::Cpy => self.cpy(operand),//tested
+            instruction::Name::Dec => self.dec(operand, addr),//tested
+            instruction::Name::Dex => self.dex(),//tested
+            instruction::Name::Dey => self.dey(),//tested
+            instruction::Name::Eor => self.eor(operand), //tested
+            instruction::Name::Inc => self.inc(operand, addr), //tested
+            instruction::Name::Inx => self.inx(),//tested
+            instruction::Name::Iny => self.iny(),//tested
+            instruction::Name::Jmp => self.jmp(addr),
+            instruction::Name::Jsr => self.jsr(),
+            instruction::Name::Lda => self.lda(operand),//tested
+            instruction::Name::Ldx => self.ldx(operand),//tested
+            instruction::Name::Ldy => self.ldy(operand),//tested
+            instruction::Name::Lsr if instruct.mode == instruction::Mode::Accumulator
+                                   => self.lsr_a(),//tested
+            instruction::Name::Lsr => self.lsr(operand, addr),//tested
+            instruction::Name::Nop => self.nop(),
+            instruction::Name::Ora => self.ora(operand),//tested
+            instruction::Name::Pha => self.pha(), //tested
+            instruction::Name::Php => self.php(),
+            instruction::Name::Pla => self.pla(), //tested
+            instruction::Name::Plp => self.plp(),
+            instruction::Name::Rol if instruct.mode == instruction::Mode::Accumulator
+                                   => self.rol_a(), //tested
+            instruction::Name::Rol => self.rol(operand, addr),//tested
+            instruction::Name::Ror if instruct.mode == instruction::Mode::Accumulator
+                                   => self.ror_a(),//tested
+            instruction::Name::Ror => self.ror(operand, addr),//tested
+            instruction::Name::Rti => self.rti(),
+            instruction::Name::Rts => self.rts(),
+            instruction::Name::Sbc => self.sbc(operand),
+            instruction::Name::Sec => self.sec(),//tested
+            instruction::Name::Sed => self.sed(),
+            instruction::Name::Sei => self.sei(),
+            instruction::Name::Sta => self.sta(addr),//tested
+            instruction::Name::Stx => self.stx(addr),//tested
+            instruction::Name::Sty => self.sty(addr),//testes,
+            instruction::Name::Tax => self.tax(),//tested
+            instruction::Name::Tay => self.tay(),//tested
+            instruction::Name::Tsx => self.tsx(),
+            instruction::Name::Txa => self.txa(),//tested
+            instruction::Name::Txs => self.txs(),
+            instruction::Name::Tya => self.tya(),//tested
+            instruction::Name::Alr => self.alr(operand),
+            instruction::Name::Anc => self.anc(operand),
+            instruction::Name::Arr => self.arr(operand),
+            instruction::Name::Axs => self.axs(operand),
+            instruction::Name::Dcp => self.dcp(operand, addr),
+            instruction::Name::Isc => self.isc(operand, addr),
+            instruction::Name::Las => self.las(operand),
+            instruction::Name::Lax => self.lax(operand),
+            instruction::Name::Rla => self.rla(operand, addr),
+            instruction::Name::Rra => self.rra(operand, addr),
+            instruction::Name::Sax => self.sax(addr),
+            instruction::Name::Slo => self.slo(operand, addr),
+            instruction::Name::Sre => self.sre(operand, addr),
+            instruction::Name::Ahx => self.unstable_store(addr, page_crossed, self.a & self.x),
+            instruction::Name::Lxa => self.lxa(operand),
+            instruction::Name::Shx => self.unstable_store(addr, page_crossed, self.x),
+            instruction::Name::Shy => self.unstable_store(addr, page_crossed, self.y),
+            instruction::Name::Tas => self.tas(addr, page_crossed),
+            instruction::Name::Xaa => self.xaa(operand),
+            instruction::Name::Brk | instruction::Name::Jam => unreachable!()
+        }
+        if !has_branched {
+            self.counter = self.counter.wrapping_add(u16::from(instruct.len - 1));
+        }
+        let is_taken_branch = instruct.mode == instruction::Mode::Relative && has_branched;
+        if is_taken_branch {
+            // the next opcode is read while the target is computed
+            let next_instruction = previous_position.wrapping_add(1);
+            self.dummy_read(next_instruction);
+            if page_crossed {
+                self.dummy_read(next_instruction & 0xFF00 | self.counter & 0x00FF);
             }
         }
+        //https://www.nesdev.org/wiki/6502_cycle_times
+        self.add_cycles(u64::from(instruct.cycles)
+            + u64::from(is_taken_branch)
+            + u64::from(page_crossed && (is_taken_branch || instruct.adds_page_cross_cycle())));
+        // CLI, SEI and PLP change I after interrupts are polled
+        let interrupt_disable = match instruct.name {
+            instruction::Name::Cli | instruction::Name::Sei | instruction::Name::Plp
+                => interrupt_disable,
+            _ => self.status.is_set(register::Status::INTERRUPT_DISABLE),
+        };
+        // a taken branch that stays on the same page doesn't poll
+        // interrupts on its last cycle, the next instruction runs first
+        if !is_taken_branch || page_crossed {
+            self.poll_interrupts(interrupt_disable);
+        }
+        (self.cycles - start_cycles) as u32
+    }
+
+    //https://www.nesdev.org/wiki/CPU_interrupts
+    fn poll_interrupts(&mut self, interrupt_disable: bool) {
+        // in cycle mode the lines are polled before the last cycle
+        let (nmi, irq) = match self.execution_mode {
+            ExecutionMode::Instruction => (self.nmi_pending, self.irq_asserted()),
+            ExecutionMode::Cycle => self.sampled_interrupts,
+        };
+        if nmi {
+            self.nmi_pending = false;
+            self.pending_interrupt = Some(NMI_VECTOR);
+        } else if !interrupt_disable && irq {
+            self.pending_interrupt = Some(IRQ_VECTOR);
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.irq_line || self.memory.irq()
+    }
+
+    fn interrupt(&mut self, vector: u16, is_break: bool) {
+        //https://www.nesdev.org/wiki/Status_flags
+        self.push_u16_on_stack(self.counter);
+        let mut status = self.status | register::Status::UNUSED;
+        status.set_or_unset_if(register::Status::BREAK, || is_break);
+        self.push_u8_on_stack(status.bits());
+        self.status.insert(register::Status::INTERRUPT_DISABLE);
+        self.add_cycles(INTERRUPT_CYCLES);
+        // an NMI occurring during the sequence hijacks IRQ and BRK
+        let vector = if vector == IRQ_VECTOR && self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_VECTOR
+        } else {
+            vector
+        };
+        self.counter = self.read_u16(vector);
     }
 
-    unsafe fn get_operand_address(&mut self, mode: &instruction::Mode) -> u16 {
-        match mode {
-            instruction::Mode::Absolute => (*self.memory).mem_read_u16(self.counter),
-            instruction::Mode::AbsoluteX => (*self.memory)
-                .mem_read_u16(self.counter)
-                .wrapping_add(self.x as u16),
-            instruction::Mode::AbsoluteY => (*self.memory)
-                .mem_read_u16(self.counter)
-                .wrapping_add(self.y as u16),
+    /// Returns the effective address and whether indexing crossed a page,
+    /// with the dummy reads the 6502 makes while computing it.
+    //https://www.nesdev.org/wiki/CPU_addressing_modes
+    fn get_operand_address(&mut self, instruct: &instruction::Instruction) -> (u16, bool) {
+        match instruct.mode {
+            // JSR pushes the return address between the reads of its operand
+            instruction::Mode::Absolute if instruct.name == instruction::Name::Jsr => {
+                (IMPLICIT_MODE_ADDR, false)
+            }
+            instruction::Mode::Absolute => (self.read_u16(self.counter), false),
+            instruction::Mode::AbsoluteX => {
+                let base = self.read_u16(self.counter);
+                self.index_address(base, self.x, instruct)
+            }
+            instruction::Mode::AbsoluteY => {
+                let base = self.read_u16(self.counter);
+                self.index_address(base, self.y, instruct)
+            }
             instruction::Mode::Indirect => {
-                let addr = (*self.memory).mem_read_u16(self.counter);
-                (*self.memory).mem_read_u16(addr)
+                let addr = self.read_u16(self.counter);
+                (self.read_pointer(addr), false)
             }
             instruction::Mode::IndirectX => {
-                let addr = (*self.memory)
-                    .mem_read_u8(self.counter)
-                    .wrapping_add(self.x);
-                (*self.memory).mem_read_u16(addr as u16)
+                let addr = self.index_zero_page(self.x);
+                (self.read_pointer(addr), false)
             }
             instruction::Mode::IndirectY => {
-                let addr =
-                    (*self.memory).mem_read_u16((*self.memory).mem_read_u8(self.counter) as u16);
-                addr.wrapping_add(self.y as u16)
+                let addr = self.read(self.counter);
+                let base = self.read_pointer(u16::from(addr));
+                self.index_address(base, self.y, instruct)
             }
-            instruction::Mode::ZeroPage => (*self.memory).mem_read_u8(self.counter) as u16,
-            instruction::Mode::ZeroPageX => (*self.memory)
-                .mem_read_u8(self.counter)
-                .wrapping_add(self.x) as u16,
-            instruction::Mode::ZeroPageY => (*self.memory)
-                .mem_read_u8(self.counter)
-                .wrapping_add(self.y) as u16,
-            instruction::Mode::Immediate => self.counter,
+            instruction::Mode::ZeroPage => (self.read(self.counter) as u16, false),
+            instruction::Mode::ZeroPageX => (self.index_zero_page(self.x), false),
+            instruction::Mode::ZeroPageY => (self.index_zero_page(self.y), false),
+            instruction::Mode::Immediate => (self.counter, false),
             instruction::Mode::Relative => {
-                let offset = (*self.memory).mem_read_u8(self.counter) as i8;
-                self.counter.wrapping_add(1).wrapping_add(offset as u16)
+                let offset = self.read(self.counter) as i8;
+                let next_instruction = self.counter.wrapping_add(1);
+                let addr = next_instruction.wrapping_add(offset as u16);
+                (addr, (next_instruction ^ addr) & 0xFF00 != 0)
             }
-            instruction::Mode::Implicit => IMPLICIT_MODE_ADDR,
-            instruction::Mode::Accumulator => IMPLICIT_MODE_ADDR,
+            instruction::Mode::Implicit => (IMPLICIT_MODE_ADDR, false),
+            instruction::Mode::Accumulator => (IMPLICIT_MODE_ADDR, false),
         }
     }
 
+    /// The index is added to the low byte first : the CPU reads that partial
+    /// address while it fixes the high byte, which reads only skip when no
+    /// page is crossed.
+    fn index_address(
+        &mut self,
+        base: u16,
+        index: u8,
+        instruct: &instruction::Instruction,
+    ) -> (u16, bool) {
+        let (addr, page_crossed) = index_address(base, index);
+        if page_crossed || !instruct.adds_page_cross_cycle() {
+            self.read(base & 0xFF00 | addr & 0x00FF);
+        }
+        (addr, page_crossed)
+    }
+
+    /// Zero page indexing reads the base address and wraps within page 0.
+    fn index_zero_page(&mut self, index: u8) -> u16 {
+        let base = self.read(self.counter);
+        self.read(u16::from(base));
+        u16::from(base.wrapping_add(index))
+    }
+
+    /// Only the low byte of a pointer is incremented : a pointer at $xxFF
+    /// gets its high byte from $xx00.
+    fn read_pointer(&mut self, addr: u16) -> u16 {
+        let hi_addr = addr & 0xFF00 | addr.wrapping_add(1) & 0x00FF;
+        u16::from_le_bytes([self.read(addr), self.read(hi_addr)])
+    }
+
     fn adc(&mut self, operand: u8) {
-        // no decimal mode.
+        if self.is_decimal_mode() {
+            self.adc_decimal(operand)
+        } else {
+            self.add(operand)
+        }
+    }
+
+    /// Binary addition, the only mode of the 2A03
+    fn add(&mut self, operand: u8) {
         let sum = self.a as u16
             + operand as u16
             + if self.status.is_set(register::Status::CARRY) {
@@ -190,12 +567,12 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn asl(&mut self, operand: u8, addr: u16) {
+    fn asl(&mut self, operand: u8, addr: u16) {
         self.status
             .set_or_unset_if(register::Status::CARRY, || operand >> 7 == 1);
         let res = operand << 1;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res)
+        self.write(addr, res)
     }
 
     fn asl_a(&mut self) {
@@ -205,53 +582,51 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    #[allow(clippy::bad_bit_mask)]
     fn bit(&mut self, operand: u8) {
         self.status
             .set_or_unset_if(register::Status::ZERO, || self.a & operand == 0);
         self.status
-            .set_or_unset_if(register::Status::NEGATIVE, || operand & 0b1000_0000 == 1);
+            .set_or_unset_if(register::Status::NEGATIVE, || operand & 0b1000_0000 != 0);
         self.status
-            .set_or_unset_if(register::Status::OVERFLOW, || operand & 0b0100_0000 == 1);
+            .set_or_unset_if(register::Status::OVERFLOW, || operand & 0b0100_0000 != 0);
     }
 
-    fn bcc(&mut self, addr: u16) {
+    fn bcc(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_unset(register::Status::CARRY))
     }
 
-    fn bcs(&mut self, addr: u16) {
+    fn bcs(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_set(register::Status::CARRY))
     }
 
-    fn beq(&mut self, addr: u16) {
+    fn beq(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_set(register::Status::ZERO))
     }
 
-    fn bmi(&mut self, addr: u16) {
+    fn bmi(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_set(register::Status::NEGATIVE))
     }
 
-    fn bne(&mut self, addr: u16) {
+    fn bne(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_unset(register::Status::ZERO))
     }
 
-    fn bpl(&mut self, addr: u16) {
+    fn bpl(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_unset(register::Status::NEGATIVE))
     }
 
-    unsafe fn brk(&mut self) {
-        //https://www.nesdev.org/wiki/Status_flags
-        self.push_u16_on_stack(self.counter);
-        self.push_u8_on_stack((self.status | register::Status::BREAK).bits());
-        // IRQ interrput vector loaded in program counter
-        // self.counter += 1;
+    fn brk(&mut self) {
+        self.dummy_read(self.counter.wrapping_add(1));
+        // the byte following BRK is skipped by RTI
+        self.counter = self.counter.wrapping_add(2);
+        self.interrupt(IRQ_VECTOR, true);
     }
 
-    fn bvc(&mut self, addr: u16) {
+    fn bvc(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_unset(register::Status::OVERFLOW))
     }
 
-    fn bvs(&mut self, addr: u16) {
+    fn bvs(&mut self, addr: u16) -> bool {
         self.branch_if(addr, |status| status.is_set(register::Status::OVERFLOW))
     }
 
@@ -283,18 +658,20 @@ impl Cpu {
         self.compare(self.y, operand);
     }
 
-    unsafe fn dec(&mut self, operand: u8, addr: u16) {
+    fn dec(&mut self, operand: u8, addr: u16) {
         let val = operand.wrapping_sub(1);
-        (*self.memory).mem_write_u8(addr, val);
+        self.write(addr, val);
         self.set_negative_and_zero_flags(val);
     }
 
     fn dex(&mut self) {
-        self.x = self.x.wrapping_sub(1)
+        self.x = self.x.wrapping_sub(1);
+        self.set_negative_and_zero_flags(self.x);
     }
 
     fn dey(&mut self) {
-        self.y = self.y.wrapping_sub(1)
+        self.y = self.y.wrapping_sub(1);
+        self.set_negative_and_zero_flags(self.y);
     }
 
     fn eor(&mut self, operand: u8) {
@@ -302,27 +679,33 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn inc(&mut self, operand: u8, addr: u16) {
+    fn inc(&mut self, operand: u8, addr: u16) {
         let val = operand.wrapping_add(1);
-        (*self.memory).mem_write_u8(addr, val);
+        self.write(addr, val);
         self.set_negative_and_zero_flags(val);
     }
 
     fn inx(&mut self) {
         self.x = self.x.wrapping_add(1);
+        self.set_negative_and_zero_flags(self.x);
     }
 
     fn iny(&mut self) {
         self.y = self.y.wrapping_add(1);
+        self.set_negative_and_zero_flags(self.y);
     }
 
     fn jmp(&mut self, addr: u16) {
         self.branch(addr);
     }
 
-    unsafe fn jsr(&mut self, addr: u16) {
-        self.push_u16_on_stack(self.counter);
-        self.branch(addr);
+    fn jsr(&mut self) {
+        let lo = self.read(self.counter);
+        self.dummy_read(self.get_stack_addr());
+        // the pushed address is the last byte of JSR, RTS adds one
+        self.push_u16_on_stack(self.counter.wrapping_add(1));
+        let hi = self.read(self.counter.wrapping_add(1));
+        self.branch(u16::from_le_bytes([lo, hi]));
     }
 
     fn lda(&mut self, operand: u8) {
@@ -340,12 +723,12 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.y);
     }
 
-    unsafe fn lsr(&mut self, operand: u8, addr: u16) {
+    fn lsr(&mut self, operand: u8, addr: u16) {
         self.status
             .set_or_unset_if(register::Status::CARRY, || operand & 1 == 1);
         let res = operand >> 1;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res);
+        self.write(addr, res);
     }
 
     fn lsr_a(&mut self) {
@@ -362,26 +745,29 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn pha(&mut self) {
+    fn pha(&mut self) {
         self.push_u8_on_stack(self.a);
     }
 
-    unsafe fn php(&mut self) {
+    fn php(&mut self) {
         //https://www.nesdev.org/wiki/Status_flags
-        self.push_u8_on_stack((self.status | register::Status::BREAK).bits());
+        self.push_u8_on_stack(
+            (self.status | register::Status::BREAK | register::Status::UNUSED).bits(),
+        );
     }
 
-    unsafe fn pla(&mut self) {
+    fn pla(&mut self) {
+        self.dummy_read(self.get_stack_addr());
         self.a = self.pull_u8_from_stack();
+        self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn plp(&mut self) {
-        //https://www.nesdev.org/wiki/Status_flags
-        self.status = register::Status::from_bits(self.pull_u8_from_stack()).unwrap()
-            & !register::Status::BREAK;
+    fn plp(&mut self) {
+        self.dummy_read(self.get_stack_addr());
+        self.status = self.pull_status_from_stack();
     }
 
-    unsafe fn rol(&mut self, operand: u8, addr: u16) {
+    fn rol(&mut self, operand: u8, addr: u16) {
         let carry = if self.status.is_set(register::Status::CARRY) {
             1
         } else {
@@ -391,7 +777,7 @@ impl Cpu {
             .set_or_unset_if(register::Status::CARRY, || operand >> 7 == 1);
         let res = (operand << 1) | carry;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res);
+        self.write(addr, res);
     }
 
     fn rol_a(&mut self) {
@@ -406,7 +792,7 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a)
     }
 
-    unsafe fn ror(&mut self, operand: u8, addr: u16) {
+    fn ror(&mut self, operand: u8, addr: u16) {
         let carry = if self.status.is_set(register::Status::CARRY) {
             1
         } else {
@@ -416,7 +802,7 @@ impl Cpu {
             .set_or_unset_if(register::Status::CARRY, || operand & 1 == 1);
         let res = operand >> 1 | carry << 7;
         self.set_negative_and_zero_flags(res);
-        (*self.memory).mem_write_u8(addr, res)
+        self.write(addr, res)
     }
 
     fn ror_a(&mut self) {
@@ -431,21 +817,28 @@ impl Cpu {
         self.set_negative_and_zero_flags(self.a)
     }
 
-    unsafe fn rti(&mut self) {
-        //https://www.nesdev.org/wiki/Status_flags
-        self.status = register::Status::from_bits(self.pull_u8_from_stack()).unwrap()
-            & !register::Status::BREAK;
+    fn rti(&mut self) {
+        self.dummy_read(self.get_stack_addr());
+        self.status = self.pull_status_from_stack();
         let addr = self.pull_u16_from_stack();
         self.branch(addr)
     }
 
-    unsafe fn rts(&mut self) {
-        let addr = self.pull_u16_from_stack().wrapping_add(1);
-        self.branch(addr)
+    fn rts(&mut self) {
+        self.dummy_read(self.get_stack_addr());
+        let addr = self.pull_u16_from_stack();
+        self.dummy_read(addr);
+        self.branch(addr.wrapping_add(1))
     }
 
     fn sbc(&mut self, operand: u8) {
-        self.adc(operand.wrapping_neg().wrapping_sub(1)); // ? I kindof get the why But i don't really understand it ... oO?
+        let difference = self.is_decimal_mode().then(|| self.sbc_decimal(operand));
+        self.add(operand.wrapping_neg().wrapping_sub(1)); // ? I kindof get the why But i don't really understand it ... oO?
+
+        // the NMOS 6502 sets the flags from the binary result
+        if let Some(difference) = difference {
+            self.a = difference;
+        }
     }
 
     fn sec(&mut self) {
@@ -460,41 +853,228 @@ impl Cpu {
         self.status.insert(register::Status::INTERRUPT_DISABLE)
     }
 
-    unsafe fn sta(&mut self, addr: u16) {
-        (*self.memory).mem_write_u8(addr, self.a)
+    fn sta(&mut self, addr: u16) {
+        self.write(addr, self.a)
     }
 
-    unsafe fn stx(&mut self, addr: u16) {
-        (*self.memory).mem_write_u8(addr, self.x)
+    fn stx(&mut self, addr: u16) {
+        self.write(addr, self.x)
     }
 
-    unsafe fn sty(&mut self, addr: u16) {
-        (*self.memory).mem_write_u8(addr, self.y)
+    fn sty(&mut self, addr: u16) {
+        self.write(addr, self.y)
     }
 
     fn tax(&mut self) {
         self.x = self.a;
+        self.set_negative_and_zero_flags(self.x);
     }
 
     fn tay(&mut self) {
         self.y = self.a;
+        self.set_negative_and_zero_flags(self.y);
     }
 
-    unsafe fn tsx(&mut self) {
+    fn tsx(&mut self) {
         self.x = self.stack_pointer;
         self.set_negative_and_zero_flags(self.x)
     }
 
     fn txa(&mut self) {
         self.a = self.x;
+        self.set_negative_and_zero_flags(self.a);
     }
 
-    unsafe fn txs(&mut self) {
+    fn txs(&mut self) {
         self.stack_pointer = self.x;
     }
 
     fn tya(&mut self) {
         self.a = self.y;
+        self.set_negative_and_zero_flags(self.a);
+    }
+
+    fn alr(&mut self, operand: u8) {
+        self.and(operand);
+        self.lsr_a();
+    }
+
+    fn anc(&mut self, operand: u8) {
+        self.and(operand);
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || (self.a as i8) < 0);
+    }
+
+    fn arr(&mut self, operand: u8) {
+        if self.is_decimal_mode() {
+            return self.arr_decimal(operand);
+        }
+        self.and(operand);
+        self.ror_a();
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || self.a & 0b0100_0000 != 0);
+        self.status.set_or_unset_if(register::Status::OVERFLOW, || {
+            (self.a >> 6 ^ self.a >> 5) & 1 != 0
+        });
+    }
+
+    fn axs(&mut self, operand: u8) {
+        let lhs = self.a & self.x;
+        self.x = lhs.wrapping_sub(operand);
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || lhs >= operand);
+        self.set_negative_and_zero_flags(self.x);
+    }
+
+    fn dcp(&mut self, operand: u8, addr: u16) {
+        let val = operand.wrapping_sub(1);
+        self.write(addr, val);
+        self.compare(self.a, val);
+    }
+
+    fn isc(&mut self, operand: u8, addr: u16) {
+        let val = operand.wrapping_add(1);
+        self.write(addr, val);
+        self.sbc(val);
+    }
+
+    fn las(&mut self, operand: u8) {
+        let val = operand & self.stack_pointer;
+        self.a = val;
+        self.x = val;
+        self.stack_pointer = val;
+        self.set_negative_and_zero_flags(val);
+    }
+
+    fn lax(&mut self, operand: u8) {
+        self.a = operand;
+        self.x = operand;
+        self.set_negative_and_zero_flags(operand);
+    }
+
+    fn rla(&mut self, operand: u8, addr: u16) {
+        let carry = u8::from(self.status.is_set(register::Status::CARRY));
+        self.rol(operand, addr);
+        self.and(operand << 1 | carry);
+    }
+
+    fn rra(&mut self, operand: u8, addr: u16) {
+        let carry = u8::from(self.status.is_set(register::Status::CARRY));
+        self.ror(operand, addr);
+        self.adc(operand >> 1 | carry << 7);
+    }
+
+    fn sax(&mut self, addr: u16) {
+        self.write(addr, self.a & self.x)
+    }
+
+    fn slo(&mut self, operand: u8, addr: u16) {
+        self.asl(operand, addr);
+        self.ora(operand << 1);
+    }
+
+    fn sre(&mut self, operand: u8, addr: u16) {
+        self.lsr(operand, addr);
+        self.eor(operand >> 1);
+    }
+
+    fn lxa(&mut self, operand: u8) {
+        self.lax((self.a | UNSTABLE_MAGIC) & operand);
+    }
+
+    fn tas(&mut self, addr: u16, page_crossed: bool) {
+        self.stack_pointer = self.a & self.x;
+        self.unstable_store(addr, page_crossed, self.stack_pointer);
+    }
+
+    fn xaa(&mut self, operand: u8) {
+        self.a = (self.a | UNSTABLE_MAGIC) & self.x & operand;
+        self.set_negative_and_zero_flags(self.a);
+    }
+
+    /// AHX, SHX, SHY and TAS store the value ANDed with the high byte of the
+    /// base address plus one, which also replaces the high byte of the
+    /// address when indexing crosses a page.
+    fn unstable_store(&mut self, addr: u16, page_crossed: bool, value: u8) {
+        let [lo, hi] = addr.to_le_bytes();
+        let base_hi = hi.wrapping_sub(u8::from(page_crossed));
+        let value = value & base_hi.wrapping_add(1);
+        let addr = if page_crossed {
+            u16::from_le_bytes([lo, value])
+        } else {
+            addr
+        };
+        self.write(addr, value)
+    }
+
+    fn is_decimal_mode(&self) -> bool {
+        self.variant == Variant::Nmos6502 && self.status.is_set(register::Status::DECIMAL)
+    }
+
+    /// N and V come from the result before the high digit is adjusted, Z from
+    /// the binary sum.
+    //http://www.6502.org/tutorials/decimal_mode.html#A
+    fn adc_decimal(&mut self, operand: u8) {
+        let carry = u8::from(self.status.is_set(register::Status::CARRY));
+        let binary = self.a.wrapping_add(operand).wrapping_add(carry);
+        let mut lo = (self.a & 0x0F) + (operand & 0x0F) + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let signed =
+            i16::from((self.a & 0xF0) as i8) + i16::from((operand & 0xF0) as i8) + i16::from(lo);
+        let mut sum = u16::from(self.a & 0xF0) + u16::from(operand & 0xF0) + u16::from(lo);
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || sum >= 0x100);
+        self.status.set_or_unset_if(register::Status::OVERFLOW, || {
+            !(-128..=127).contains(&signed)
+        });
+        self.status
+            .set_or_unset_if(register::Status::NEGATIVE, || signed & 0x80 != 0);
+        self.status
+            .set_or_unset_if(register::Status::ZERO, || binary == 0);
+        self.a = sum as u8;
+    }
+
+    //http://www.6502.org/tutorials/decimal_mode.html#A
+    fn sbc_decimal(&self, operand: u8) -> u8 {
+        let borrow = i16::from(self.status.is_unset(register::Status::CARRY));
+        let mut lo = i16::from(self.a & 0x0F) - i16::from(operand & 0x0F) - borrow;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0F) - 0x10;
+        }
+        let mut difference = i16::from(self.a & 0xF0) - i16::from(operand & 0xF0) + lo;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+        difference as u8
+    }
+
+    /// ARR fixes each digit of the rotated value like ADC would.
+    //https://www.nesdev.org/6502_cpu.txt
+    fn arr_decimal(&mut self, operand: u8) {
+        let and = self.a & operand;
+        let carry = u8::from(self.status.is_set(register::Status::CARRY));
+        let mut res = and >> 1 | carry << 7;
+        self.status
+            .set_or_unset_if(register::Status::NEGATIVE, || carry != 0);
+        self.status
+            .set_or_unset_if(register::Status::ZERO, || res == 0);
+        self.status
+            .set_or_unset_if(register::Status::OVERFLOW, || (and ^ res) & 0x40 != 0);
+        if (and & 0x0F) + (and & 0x01) > 0x05 {
+            res = res & 0xF0 | res.wrapping_add(0x06) & 0x0F;
+        }
+        let fixes_high_digit = u16::from(and & 0xF0) + u16::from(and & 0x10) > 0x50;
+        if fixes_high_digit {
+            res = res.wrapping_add(0x60);
+        }
+        self.status
+            .set_or_unset_if(register::Status::CARRY, || fixes_high_digit);
+        self.a = res;
     }
 
     fn set_negative_and_zero_flags(&mut self, operation_res: u8) {
@@ -504,49 +1084,99 @@ impl Cpu {
             .set_or_unset_if(register::Status::ZERO, || operation_res == 0);
     }
 
-    fn branch_if(&mut self, addr: u16, predicate: impl Fn(&register::Status) -> bool) {
-        if predicate(&self.status) {
+    /// Returns whether the branch was taken.
+    fn branch_if(&mut self, addr: u16, predicate: impl Fn(&register::Status) -> bool) -> bool {
+        let is_taken = predicate(&self.status);
+        if is_taken {
             self.branch(addr)
         }
+        is_taken
     }
 
     fn branch(&mut self, addr: u16) {
         self.counter = addr;
     }
 
-    fn has_branched(&self, previous_addr: u16) -> bool {
-        previous_addr != self.counter
-    }
-
     fn compare(&mut self, lhs: u8, rhs: u8) {
         let val = lhs.wrapping_sub(rhs);
         self.status
-            .set_or_unset_if(register::Status::CARRY, || val as i8 >= 0);
+            .set_or_unset_if(register::Status::CARRY, || lhs >= rhs);
         self.set_negative_and_zero_flags(val);
     }
 
-    unsafe fn push_u8_on_stack(&mut self, byte: u8) {
-        (*self.memory).mem_write_u8(self.get_stack_addr(), byte);
+    fn push_u8_on_stack(&mut self, byte: u8) {
+        self.write(self.get_stack_addr(), byte);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
-    unsafe fn push_u16_on_stack(&mut self, addr: u16) {
-        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
-        (*self.memory).mem_write_u16(self.get_stack_addr(), addr);
-        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    fn push_u16_on_stack(&mut self, addr: u16) {
+        let [lo, hi] = addr.to_le_bytes();
+        self.push_u8_on_stack(hi);
+        self.push_u8_on_stack(lo);
     }
 
-    unsafe fn pull_u8_from_stack(&mut self) -> u8 {
+    fn pull_u8_from_stack(&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        let res = (*self.memory).mem_read_u8(self.get_stack_addr());
-        res
+        self.read(self.get_stack_addr())
     }
 
-    unsafe fn pull_u16_from_stack(&mut self) -> u16 {
-        self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        let res = (*self.memory).mem_read_u16(self.get_stack_addr());
-        self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        res
+    /// B doesn't exist in the register and bit 5 always reads as set.
+    //https://www.nesdev.org/wiki/Status_flags
+    fn pull_status_from_stack(&mut self) -> register::Status {
+        let status = register::Status::from_bits_truncate(self.pull_u8_from_stack());
+        (status - register::Status::BREAK) | register::Status::UNUSED
+    }
+
+    fn pull_u16_from_stack(&mut self) -> u16 {
+        let lo = self.pull_u8_from_stack();
+        let hi = self.pull_u8_from_stack();
+        u16::from_le_bytes([lo, hi])
+    }
+
+    /// One cycle of the cycle mode : the memory ticks the other chips, then
+    /// the interrupt lines are sampled for the edge detector.
+    #[inline]
+    fn cycle(&mut self) {
+        if self.execution_mode == ExecutionMode::Cycle {
+            self.sampled_interrupts = (self.nmi_pending, self.irq_asserted());
+            self.memory.tick();
+            self.cycles += 1 + u64::from(self.memory.take_stall_cycles());
+            let nmi = self.nmi_input || self.memory.nmi();
+            self.detect_nmi_edge(nmi);
+        }
+    }
+
+    /// Cycles counted from the tables, the cycle mode counts bus accesses.
+    #[inline]
+    fn add_cycles(&mut self, cycles: u64) {
+        if self.execution_mode == ExecutionMode::Instruction {
+            self.cycles += cycles;
+        }
+    }
+
+    #[inline]
+    fn read(&mut self, addr: u16) -> u8 {
+        self.cycle();
+        self.memory.mem_read_u8(addr)
+    }
+
+    fn read_u16(&mut self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.read(addr), self.read(addr.wrapping_add(1))])
+    }
+
+    /// Reads whose value is discarded, only made in cycle mode to keep the
+    /// instruction mode fast.
+    #[inline]
+    fn dummy_read(&mut self, addr: u16) {
+        if self.execution_mode == ExecutionMode::Cycle {
+            self.read(addr);
+        }
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, data: u8) {
+        self.cycle();
+        self.memory.mem_write_u8(addr, data)
     }
 
     pub fn get_stack_addr(&self) -> u16 {
@@ -554,5 +1184,18 @@ impl Cpu {
     }
 }
 
+fn index_address(base: u16, index: u8) -> (u16, bool) {
+    let addr = base.wrapping_add(u16::from(index));
+    (addr, (base ^ addr) & 0xFF00 != 0)
+}
+
+#[cfg(test)]
+mod decimal_test;
+#[cfg(test)]
+mod functional_test;
+#[cfg(test)]
+mod nestest;
+#[cfg(test)]
+mod processor_tests;
 #[cfg(test)]
 mod test;
@@ -0,0 +1,122 @@
+//! Runs nestest in automation mode and compares each instruction with the
+//! golden log, in the Nintendulator trace format. The values at the effective
+//! address and the marks of the unofficial opcodes are left out of the
+//! comparison, like they are from `TraceFormat::Nintendulator`.
+//https://www.qmtpro.com/~nes/misc/nestest.txt
+use crate::trace::TraceFormat;
+use crate::traits::Memory;
+use crate::Nes;
+use std::ops::Range;
+use std::path::Path;
+
+const AUTOMATION_START: u16 = 0xC000;
+const CONTEXT_LINES: usize = 5;
+/// nestest stores the result codes of the official and unofficial tests here
+const RESULT_ADDR: u16 = 0x0002;
+/// Column of the mark of the unofficial opcodes in the golden log, the
+/// disassembly follows it
+const MARK_COLUMN: usize = 15;
+const DISASSEMBLY_COLUMNS: Range<usize> = 16..48;
+
+/// Drops the mark of the unofficial opcodes and what follows the instruction
+/// in the disassembly column of a golden log line, ` @ 11 = 00` in
+/// `STA $10,X @ 11 = 00`.
+fn strip_effective_address(line: &str) -> String {
+    let (Some(head), Some(disassembly), Some(tail)) = (
+        line.get(..MARK_COLUMN),
+        line.get(DISASSEMBLY_COLUMNS),
+        line.get(DISASSEMBLY_COLUMNS.end..),
+    ) else {
+        return line.to_string();
+    };
+    let instruction = disassembly
+        .split(" = ")
+        .next()
+        .and_then(|instruction| instruction.split(" @ ").next())
+        .unwrap_or_default()
+        .trim_end();
+    format!(
+        "{head} {instruction:<width$}{tail}",
+        width = DISASSEMBLY_COLUMNS.len()
+    )
+}
+
+/// Compares the trace of each instruction with the golden log and reports
+/// the first line that differs along with the lines executed before it.
+fn run_against_golden_log(rom: &[u8], golden_log: &str) -> Result<(), String> {
+    let mut nes = Nes::from_rom(rom).map_err(|err| err.to_string())?;
+    nes.cpu.counter = AUTOMATION_START;
+    let mut history = Vec::new();
+    for (number, expected) in golden_log.lines().enumerate() {
+        let actual = nes.cpu.trace_entry().format(TraceFormat::Nintendulator);
+        if actual.trim_end() != strip_effective_address(expected).trim_end() {
+            let context = history[history.len().saturating_sub(CONTEXT_LINES)..].join("\n");
+            return Err(format!(
+                "line {} differs\n{context}\nexpected: {expected}\nactual:   {actual}",
+                number + 1
+            ));
+        }
+        history.push(actual);
+        nes.step();
+    }
+    let result = nes.cpu.memory.mem_read_u16(RESULT_ADDR);
+    if result != 0 {
+        return Err(format!("nestest reported error codes {result:04X}"));
+    }
+    Ok(())
+}
+
+/// Builds an NROM image whose PRG starts at $C000 with `program`
+fn create_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0];
+    rom.resize(16, 0);
+    let mut prg = vec![0; crate::cartridge::PRG_ROM_BANK_SIZE];
+    prg[..program.len()].copy_from_slice(program);
+    rom.extend(prg);
+    rom.extend(vec![0; crate::cartridge::CHR_ROM_BANK_SIZE]);
+    rom
+}
+
+#[test]
+fn test_trace_format() {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x01,         // LDX #$01
+        0x95, 0x10,         // STA $10,X
+        0xA1, 0x80,         // LDA ($80,X)
+        0x04, 0x10,         // NOP $10
+    ];
+    let golden_log = "\
+C000  A2 01     LDX #$01                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C002  95 10     STA $10,X @ 11 = 00             A:00 X:01 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9
+C004  A1 80     LDA ($80,X) @ 81 = 0000 = 00    A:00 X:01 Y:00 P:24 SP:FD PPU:  0, 39 CYC:13
+C006  04 10    *NOP $10 = 00                    A:00 X:01 Y:00 P:26 SP:FD PPU:  0, 57 CYC:19";
+    assert_eq!(
+        Ok(()),
+        run_against_golden_log(&create_rom(&program), golden_log)
+    );
+}
+
+#[test]
+fn test_reports_first_difference() {
+    let golden_log = "\
+C000  A2 01     LDX #$01                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C002  E8        INX                             A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9";
+    let error = run_against_golden_log(&create_rom(&[0xA2, 0x01, 0xE8]), golden_log);
+    assert!(error.unwrap_err().starts_with("line 2 differs"));
+}
+
+/// nestest.nes and nestest.log aren't distributed with the sources, put them
+/// in tests/roms and run `cargo test -- --ignored`.
+#[test]
+#[ignore = "needs tests/roms/nestest.nes and nestest.log"]
+fn test_nestest_golden_log() {
+    let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+    let rom = std::fs::read(roms.join("nestest.nes"))
+        .unwrap_or_else(|err| panic!("no nestest.nes in {roms:?} : {err}"));
+    let golden_log = std::fs::read_to_string(roms.join("nestest.log"))
+        .unwrap_or_else(|err| panic!("no nestest.log in {roms:?} : {err}"));
+    if let Err(report) = run_against_golden_log(&rom, &golden_log) {
+        panic!("{report}");
+    }
+}
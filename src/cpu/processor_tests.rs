@@ -0,0 +1,315 @@
+//! Runs Tom Harte's ProcessorTests : for each opcode, 10,000 instructions
+//! given as initial and final states along with every bus cycle.
+//https://github.com/SingleStepTests/ProcessorTests/tree/main/6502
+use super::instruction::{decode, Name};
+use super::{register, Cpu, ExecutionMode, FlatMemory, Variant};
+use crate::traits::Memory;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const TESTS_DIR_VAR: &str = "PROCESSOR_TESTS_DIR";
+const BUS_CYCLES_VAR: &str = "PROCESSOR_TESTS_BUS_CYCLES";
+const DEFAULT_TESTS_DIR: &str = "tests/ProcessorTests/6502/v1";
+const MAX_REPORTED_FAILURES: usize = 3;
+/// B and bit 5 only exist on the stack, they aren't part of the comparison
+const IGNORED_FLAGS: register::Status = register::Status::from_bits_truncate(
+    register::Status::BREAK.bits() | register::Status::UNUSED.bits(),
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Access {
+    Read,
+    Write,
+}
+
+type BusCycle = (u16, u8, Access);
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<BusCycle>,
+}
+
+/// Flat 64K memory recording every access made by the CPU
+struct RecordingMemory {
+    ram: FlatMemory,
+    bus_cycles: Vec<BusCycle>,
+}
+
+impl RecordingMemory {
+    fn new() -> Self {
+        Self {
+            ram: FlatMemory::new(),
+            bus_cycles: Vec::new(),
+        }
+    }
+}
+
+impl Memory for RecordingMemory {
+    fn load(&mut self, data: &[u8], dest: u16) {
+        self.ram.load(data, dest);
+    }
+
+    fn mem_read_u8(&mut self, addr: u16) -> u8 {
+        let value = self.ram.mem_read_u8(addr);
+        self.bus_cycles.push((addr, value, Access::Read));
+        value
+    }
+
+    fn mem_write_u8(&mut self, addr: u16, byte: u8) {
+        self.ram.mem_write_u8(addr, byte);
+        self.bus_cycles.push((addr, byte, Access::Write));
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.ram.peek(addr)
+    }
+}
+
+/// Executes a test case and describes the first difference found
+fn run_test_case(test: &TestCase, check_bus_cycles: bool) -> Result<(), String> {
+    let mut memory = RecordingMemory::new();
+    for &(addr, value) in &test.initial.ram {
+        memory.ram.memory[usize::from(addr)] = value;
+    }
+    // only the cycle mode makes every dummy access
+    let execution_mode = if check_bus_cycles {
+        ExecutionMode::Cycle
+    } else {
+        ExecutionMode::Instruction
+    };
+    let mut cpu = Cpu::with_execution_mode(&mut memory, execution_mode);
+    // the tests were recorded on an NMOS 6502, with decimal mode
+    cpu.set_variant(Variant::Nmos6502);
+    cpu.counter = test.initial.pc;
+    cpu.stack_pointer = test.initial.s;
+    cpu.a = test.initial.a;
+    cpu.x = test.initial.x;
+    cpu.y = test.initial.y;
+    cpu.status = register::Status::from_bits_truncate(test.initial.p);
+    let cycles = cpu.step();
+
+    let expected = &test.expected;
+    let status = (cpu.status - IGNORED_FLAGS).bits();
+    let expected_status = (register::Status::from_bits_truncate(expected.p) - IGNORED_FLAGS).bits();
+    let registers = [
+        ("PC", cpu.counter, expected.pc),
+        ("S", cpu.stack_pointer.into(), expected.s.into()),
+        ("A", cpu.a.into(), expected.a.into()),
+        ("X", cpu.x.into(), expected.x.into()),
+        ("Y", cpu.y.into(), expected.y.into()),
+        ("P", status.into(), expected_status.into()),
+        ("cycles", cycles as u16, test.cycles.len() as u16),
+    ];
+    for (register, actual, expected) in registers {
+        if actual != expected {
+            return Err(format!(
+                "{} : {register} is {actual:04X}, expected {expected:04X}",
+                test.name
+            ));
+        }
+    }
+    for &(addr, value) in &expected.ram {
+        let actual = memory.ram.memory[usize::from(addr)];
+        if actual != value {
+            return Err(format!(
+                "{} : ${addr:04X} is {actual:02X}, expected {value:02X}",
+                test.name
+            ));
+        }
+    }
+    if check_bus_cycles && memory.bus_cycles != test.cycles {
+        return Err(format!(
+            "{} : bus cycles are {:?}, expected {:?}",
+            test.name, memory.bus_cycles, test.cycles
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every test case of an opcode file and returns the failures
+fn run_test_file(path: &Path, check_bus_cycles: bool) -> Vec<String> {
+    let json = std::fs::read_to_string(path).unwrap();
+    let tests: Vec<TestCase> = serde_json::from_str(&json).unwrap();
+    tests
+        .iter()
+        .filter_map(|test| run_test_case(test, check_bus_cycles).err())
+        .collect()
+}
+
+fn tests_dir() -> PathBuf {
+    std::env::var_os(TESTS_DIR_VAR).map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_TESTS_DIR),
+        PathBuf::from,
+    )
+}
+
+#[test]
+fn test_recorded_bus_cycles() {
+    let json = r#"{
+        "name": "ad 34 12",
+        "initial": { "pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+            "ram": [[512, 173], [513, 52], [514, 18], [4660, 128]] },
+        "final": { "pc": 515, "s": 253, "a": 128, "x": 0, "y": 0, "p": 164,
+            "ram": [[512, 173], [513, 52], [514, 18], [4660, 128]] },
+        "cycles": [[512, 173, "read"], [513, 52, "read"], [514, 18, "read"], [4660, 128, "read"]]
+    }"#;
+    let test: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(Ok(()), run_test_case(&test, true));
+}
+
+#[test]
+fn test_reports_register_difference() {
+    let json = r#"{
+        "name": "e8",
+        "initial": { "pc": 512, "s": 253, "a": 0, "x": 41, "y": 0, "p": 36, "ram": [[512, 232]] },
+        "final": { "pc": 513, "s": 253, "a": 0, "x": 43, "y": 0, "p": 36, "ram": [[512, 232]] },
+        "cycles": [[512, 232, "read"], [513, 0, "read"]]
+    }"#;
+    let test: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        Err("e8 : X is 002A, expected 002B".to_string()),
+        run_test_case(&test, false)
+    );
+}
+
+#[test]
+fn test_indexed_store_dummy_read() {
+    // STA $12F0,X with X = $20 reads $1210 before writing $1310
+    let json = r#"{
+        "name": "9d f0 12",
+        "initial": { "pc": 512, "s": 253, "a": 42, "x": 32, "y": 0, "p": 36,
+            "ram": [[512, 157], [513, 240], [514, 18], [4624, 7]] },
+        "final": { "pc": 515, "s": 253, "a": 42, "x": 32, "y": 0, "p": 36,
+            "ram": [[4624, 7], [4880, 42]] },
+        "cycles": [[512, 157, "read"], [513, 240, "read"], [514, 18, "read"],
+            [4624, 7, "read"], [4880, 42, "write"]]
+    }"#;
+    let test: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(Ok(()), run_test_case(&test, true));
+}
+
+#[test]
+fn test_indexed_read_dummy_read_on_page_cross() {
+    // LDA ($10),Y with $10 pointing to $12F0 and Y = $20
+    let json = r#"{
+        "name": "b1 10",
+        "initial": { "pc": 512, "s": 253, "a": 0, "x": 0, "y": 32, "p": 36,
+            "ram": [[512, 177], [513, 16], [16, 240], [17, 18], [4624, 7], [4880, 42]] },
+        "final": { "pc": 514, "s": 253, "a": 42, "x": 0, "y": 32, "p": 36,
+            "ram": [[4880, 42]] },
+        "cycles": [[512, 177, "read"], [513, 16, "read"], [16, 240, "read"], [17, 18, "read"],
+            [4624, 7, "read"], [4880, 42, "read"]]
+    }"#;
+    let test: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(Ok(()), run_test_case(&test, true));
+}
+
+#[test]
+fn test_read_modify_write_double_write() {
+    // INC $10,X with X = 1 reads $10, then writes $11 twice
+    let json = r#"{
+        "name": "f6 10",
+        "initial": { "pc": 512, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+            "ram": [[512, 246], [513, 16], [16, 0], [17, 41]] },
+        "final": { "pc": 514, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+            "ram": [[17, 42]] },
+        "cycles": [[512, 246, "read"], [513, 16, "read"], [16, 0, "read"], [17, 41, "read"],
+            [17, 41, "write"], [17, 42, "write"]]
+    }"#;
+    let test: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(Ok(()), run_test_case(&test, true));
+}
+
+#[test]
+fn test_cycle_mode_bus_cycles() {
+    let tests = [
+        // JSR $1234 reads the stack before pushing the return address
+        r#"{
+            "name": "20 34 12",
+            "initial": { "pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                "ram": [[512, 32], [513, 52], [514, 18], [509, 0]] },
+            "final": { "pc": 4660, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36,
+                "ram": [[509, 2], [508, 2]] },
+            "cycles": [[512, 32, "read"], [513, 52, "read"], [509, 0, "read"],
+                [509, 2, "write"], [508, 2, "write"], [514, 18, "read"]]
+        }"#,
+        // RTS reads the returned address before incrementing it
+        r#"{
+            "name": "60",
+            "initial": { "pc": 768, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36,
+                "ram": [[768, 96], [769, 0], [507, 0], [508, 2], [509, 2], [514, 18]] },
+            "final": { "pc": 515, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [] },
+            "cycles": [[768, 96, "read"], [769, 0, "read"], [507, 0, "read"],
+                [508, 2, "read"], [509, 2, "read"], [514, 18, "read"]]
+        }"#,
+        // BNE to the next page reads the next opcode, then the wrong page
+        r#"{
+            "name": "d0 10",
+            "initial": { "pc": 752, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                "ram": [[752, 208], [753, 16], [754, 0], [514, 0]] },
+            "final": { "pc": 770, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [] },
+            "cycles": [[752, 208, "read"], [753, 16, "read"], [754, 0, "read"], [514, 0, "read"]]
+        }"#,
+    ];
+    for json in tests {
+        let test: TestCase = serde_json::from_str(json).unwrap();
+        assert_eq!(Ok(()), run_test_case(&test, true));
+    }
+}
+
+/// The test files aren't distributed with the sources, put the JSON files of
+/// the 6502 set in tests/ProcessorTests/6502/v1 or point `PROCESSOR_TESTS_DIR`
+/// to their directory, then run
+/// `cargo test -- --ignored`. Setting `PROCESSOR_TESTS_BUS_CYCLES` runs the
+/// cycle mode and compares every bus access.
+#[test]
+#[ignore = "needs the ProcessorTests 6502 JSON files in tests/ProcessorTests/6502/v1 or PROCESSOR_TESTS_DIR"]
+fn test_processor_tests() {
+    let dir = tests_dir();
+    let entries =
+        std::fs::read_dir(&dir).unwrap_or_else(|err| panic!("no test files in {dir:?} : {err}"));
+    let check_bus_cycles = std::env::var_os(BUS_CYCLES_VAR).is_some();
+    let mut paths: Vec<PathBuf> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no test files in {dir:?}");
+
+    let mut report = Vec::new();
+    for path in paths {
+        let opcode = path.file_stem().and_then(|stem| stem.to_str());
+        let opcode = opcode.and_then(|stem| u8::from_str_radix(stem, 16).ok());
+        // JAM keeps the bus busy forever, there is no final state to compare
+        let is_jam = opcode.is_some_and(|opcode| decode(opcode).unwrap().name == Name::Jam);
+        if is_jam {
+            continue;
+        }
+        let failures = run_test_file(&path, check_bus_cycles);
+        if !failures.is_empty() {
+            report.push(format!(
+                "{} failed {} times",
+                path.display(),
+                failures.len()
+            ));
+            report.extend(failures.into_iter().take(MAX_REPORTED_FAILURES));
+        }
+    }
+    assert!(report.is_empty(), "\n{}", report.join("\n"));
+}
@@ -2,34 +2,48 @@ use super::*;
 use crate::traits::Memory;
 
 struct MemoryMock {
-    pub memory: [u8; 0xFFFF],
+    pub memory: [u8; 0x10000],
 }
 
 impl MemoryMock {
     pub fn new(program: &[u8], origin: u16) -> Self {
         let mut mock = Self {
-            memory: [0x00; 0xFFFF],
+            memory: [0x00; 0x10000],
         };
-        unsafe {
-            mock.load(program, origin);
-            mock.mem_write_u16(0xFFFC, origin);
-        }
+        mock.load(program, origin);
+        mock.mem_write_u16(0xFFFC, origin);
         mock
     }
 }
 
 impl Memory for MemoryMock {
-    unsafe fn load(&mut self, data: &[u8], dest: u16) {
+    fn load(&mut self, data: &[u8], dest: u16) {
         self.memory[usize::from(dest)..usize::from(dest) + data.len()].copy_from_slice(data);
     }
 
-    unsafe fn mem_read_u8(&mut self, addr: u16) -> u8 {
+    fn mem_read_u8(&mut self, addr: u16) -> u8 {
         self.memory[usize::from(addr)]
     }
 
-    unsafe fn mem_write_u8(&mut self, addr: u16, byte: u8) {
+    fn mem_write_u8(&mut self, addr: u16, byte: u8) {
         self.memory[usize::from(addr)] = byte
     }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
+    }
+}
+
+/// Runs from the reset vector until a BRK has been executed
+fn run_until_brk(cpu: &mut Cpu<MemoryMock>) {
+    cpu.counter = cpu.memory.mem_read_u16(PROGRAM_POINTER);
+    loop {
+        let is_brk = cpu.pending_interrupt.is_none() && cpu.memory.mem_read_u8(cpu.counter) == 0;
+        cpu.step();
+        if is_brk || cpu.is_halted() {
+            break;
+        }
+    }
 }
 
 fn create_mock_from_script(script: &str) -> MemoryMock {
@@ -69,160 +83,154 @@ end:
 
 #[test]
 fn lda_immediate_and_sta_zero_page() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #10 ; load 10 into register A
            STA $5  ; store register A at address 0x00 + 0x05 = 0x05
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() };
-    assert_eq!(10, mock.memory[0x0005])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(10, cpu.memory.memory[0x0005])
 }
 
 #[test]
 fn lda_absolute_and_sta_absolute() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $1234 ; load A at $1234
            STA $4321 ; store register A at 4321
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x1234, 42);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x4321])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x1234, 42);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x4321])
 }
 
 #[test]
 fn ldx_immediate_and_stx_zero_page() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #25 ; load 25 into register X
            STX $9  ; store register X at address 0x0 + 0x9 = 0x09
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() };
-    assert_eq!(25, mock.memory[0x0009])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(25, cpu.memory.memory[0x0009])
 }
 
 #[test]
 fn ldx_absolute_and_stx_absolute() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX $2341 ; load X at $2341
            STX $3214 ; store register X at 3214
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x2341, 99);
-        cpu.run()
-    }
-    assert_eq!(99, mock.memory[0x3214])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x2341, 99);
+    run_until_brk(&mut cpu);
+    assert_eq!(99, cpu.memory.memory[0x3214])
 }
 
 #[test]
 fn ldy_immediate_and_sty_zero_page() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #33 ; load 33 into register Y
            STY $2A  ; store register A at address 0x00 + 0X2A = 0x2A
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() };
-    assert_eq!(33, mock.memory[0x002A])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(33, cpu.memory.memory[0x002A])
 }
 
 #[test]
 fn ldy_absolute_and_sty_absolute() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY $3412 ; load T at $3412
            STY $2143 ; store register X at $2143
            BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x3412, 89);
-        cpu.run()
-    }
-    assert_eq!(89, mock.memory[0x2143])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x3412, 89);
+    run_until_brk(&mut cpu);
+    assert_eq!(89, cpu.memory.memory[0x2143])
 }
 
 #[test]
 fn bcc_forward() {
     let script = create_branch_forward_test_scipt("BCC", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(33, cpu.memory.memory[0x55])
 }
 
 #[test]
 fn bcc_backward() {
     let script = create_branch_backward_test_scipt("BCC");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn bpl_forward() {
     let script = create_branch_forward_test_scipt("BPL", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(33, cpu.memory.memory[0x55])
 }
 
 #[test]
 fn bpl_backward() {
     let script = create_branch_backward_test_scipt("BPL");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn bvc_forward() {
     let script = create_branch_forward_test_scipt("BVC", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(33, cpu.memory.memory[0x55])
 }
 
 #[test]
 fn bvc_backward() {
     let script = create_branch_backward_test_scipt("BVC");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn bne_forward() {
     let script = create_branch_forward_test_scipt("BNE", 10);
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(33, mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(33, cpu.memory.memory[0x55])
 }
 
 #[test]
 fn bne_backward() {
     let script = create_branch_backward_test_scipt("BNE");
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn beq_forward() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#" LDA #0
     BEQ forward
     LDY #99
@@ -231,14 +239,14 @@ forward:
     LDY #42
     STY $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn beq_backward() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"BCC forward
 backward:
     LDX #42
@@ -253,35 +261,35 @@ forward:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn bmi_forward() {
     let script = create_branch_forward_test_scipt("BMI", 10u8.wrapping_neg());
-    let mut mock = create_mock_from_script(&script);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(10u8.wrapping_neg(), mock.memory[0x55])
+    let mock = create_mock_from_script(&script);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(10u8.wrapping_neg(), cpu.memory.memory[0x55])
 }
 
 #[test]
 fn adc_without_carry() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #30
     ADC #12
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn adc_signed_without_carry() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         format!(
             r#"LDA #30
     ADC #{}
@@ -290,14 +298,14 @@ fn adc_signed_without_carry() {
         )
         .as_ref(),
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(10u8.wrapping_neg(), mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(10u8.wrapping_neg(), cpu.memory.memory[0x42])
 }
 
 #[test]
 fn adc_with_carry() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #255
     ADC #2
     BCS end
@@ -306,14 +314,14 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn adc_with_overflow() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #80
     ADC #80
     BVS end
@@ -322,38 +330,38 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_and_1() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #%11110000
     AND #%00001111
     STA $00"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(0b0000_0000, mock.memory[0x00])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0000_0000, cpu.memory.memory[0x00])
 }
 
 #[test]
 fn test_and_2() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #%01010101
     AND #%10011001
     STA $00"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(0b0001_0001, mock.memory[0x00])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0001_0001, cpu.memory.memory[0x00])
 }
 
 #[test]
 fn test_sec() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     BCS good
     LDX #99
@@ -365,14 +373,14 @@ good:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x1ABC])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x1ABC])
 }
 
 #[test]
 fn test_clc() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     CLC
     BCC good
@@ -385,14 +393,14 @@ good:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x1ABC])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x1ABC])
 }
 
 #[test]
 fn test_clv() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #80
     ADC #80
     CLV
@@ -406,14 +414,14 @@ good:
 end:
     BRK"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x1ABC])
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x1ABC])
 }
 
 #[test]
 fn test_asl_a_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $05
     ASL A
     STA $10
@@ -424,18 +432,16 @@ end:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x05, 0b1010_1010);
-        cpu.run()
-    }
-    assert_eq!(0b0101_0100, mock.memory[0x10]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x05, 0b1010_1010);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0101_0100, cpu.memory.memory[0x10]);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_asl_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"ASL $05
     LDA $05
     STA $10
@@ -446,18 +452,16 @@ end:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x05, 0b1010_1010);
-        cpu.run()
-    }
-    assert_eq!(0b0101_0100, mock.memory[0x10]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x05, 0b1010_1010);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0101_0100, cpu.memory.memory[0x10]);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cmp_bmi() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #10
     CMP $00
     BMI inferior
@@ -467,17 +471,15 @@ inferior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cmp_bpl() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #20
     CMP $00
     BPL superior
@@ -487,17 +489,15 @@ superior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cmp_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #15
     CMP $00
     BEQ equal
@@ -507,17 +507,15 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cpx_bmi() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #10
     CPX $00
     BMI inferior
@@ -527,17 +525,15 @@ inferior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cpx_bpl() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #20
     CPX $00
     BPL superior
@@ -547,17 +543,15 @@ superior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cpx_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #15
     CPX $00
     BEQ equal
@@ -567,17 +561,15 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cpy_bmi() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #10
     CPY $00
     BMI inferior
@@ -587,17 +579,15 @@ inferior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cpy_bpl() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #20
     CPY $00
     BPL superior
@@ -607,17 +597,15 @@ superior:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_cpy_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #15
     CPY $00
     BEQ equal
@@ -627,17 +615,15 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 15);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 15);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_dec_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"DEC $00
     LDA $00
     CMP #9
@@ -648,17 +634,15 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 10);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 10);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_dex_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX $00
     DEX
     CPX #9
@@ -669,17 +653,15 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 10);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 10);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_dey_beq() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY $00
     DEY
     CPY #9
@@ -690,73 +672,63 @@ equal:
     LDX #42
     STX $0042"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x00, 10);
-        cpu.run()
-    }
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x00, 10);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_eor() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $12
     EOR #%10101010
     STA $12"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 0b11001100);
-        cpu.run()
-    }
-    assert_eq!(0b01100110, mock.memory[0x12])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x12, 0b11001100);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b01100110, cpu.memory.memory[0x12])
 }
 
 #[test]
 fn test_inc() {
-    let mut mock = create_mock_from_script(r#"INC $12"#);
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 9);
-        cpu.run()
-    }
-    assert_eq!(10, mock.memory[0x12])
+    let mock = create_mock_from_script(r#"INC $12"#);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x12, 9);
+    run_until_brk(&mut cpu);
+    assert_eq!(10, cpu.memory.memory[0x12])
 }
 
 #[test]
 fn test_inx() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX $12
     INX
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 9);
-        cpu.run()
-    }
-    assert_eq!(10, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x12, 9);
+    run_until_brk(&mut cpu);
+    assert_eq!(10, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_iny() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY $12
     INY
     STY $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0x12, 9);
-        cpu.run()
-    }
-    assert_eq!(10, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0x12, 9);
+    run_until_brk(&mut cpu);
+    assert_eq!(10, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_lsr_a_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $AB
     LSR A
     STA $AB
@@ -767,18 +739,16 @@ carryset:
     LDX #42
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1011);
-        cpu.run()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1010_1011);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0101_0101, cpu.memory.memory[0xAB]);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_lsr_and_bcs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LSR $AB
     BCS carryset
     LDX #99
@@ -787,58 +757,54 @@ carryset:
     LDX #42
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1011);
-        cpu.run()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42])
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1010_1011);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0101_0101, cpu.memory.memory[0xAB]);
+    assert_eq!(42, cpu.memory.memory[0x42])
 }
 
 #[test]
 fn test_ora() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA $AB
     ORA #%00001111
     STA $BA"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1111_0000);
-        cpu.run()
-    }
-    assert_eq!(0b1111_1111, mock.memory[0xBA]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1111_0000);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b1111_1111, cpu.memory.memory[0xBA]);
 }
 
 #[test]
 fn test_pha() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     PHA"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x01FF]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x01FF]);
 }
 
 #[test]
 fn test_pla() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     PHA
     LDA #0
     PLA
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_rol_a() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     LDA $AB
     ROL A
@@ -850,18 +816,16 @@ end:
     LDA #42
     STA $AB"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.run()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0x42]);
-    assert_eq!(42, mock.memory[0xAB]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1010_1010);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0101_0101, cpu.memory.memory[0x42]);
+    assert_eq!(42, cpu.memory.memory[0xAB]);
 }
 
 #[test]
 fn test_rol() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     ROL $AB
     BCS end
@@ -871,18 +835,16 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.run()
-    }
-    assert_eq!(0b0101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1010_1010);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b0101_0101, cpu.memory.memory[0xAB]);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_ror_a() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     LDA $AB
     ROR A
@@ -895,18 +857,16 @@ end:
     LDA #42
     STA $AB"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.run()
-    }
-    assert_eq!(0b1101_0101, mock.memory[0x42]);
-    assert_eq!(42, mock.memory[0xAB]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1010_1010);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b1101_0101, cpu.memory.memory[0x42]);
+    assert_eq!(42, cpu.memory.memory[0xAB]);
 }
 
 #[test]
 fn test_ror() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"SEC
     ROR $AB
     BCC end
@@ -917,66 +877,64 @@ end:
     LDA #42
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe {
-        mock.mem_write_u8(0xAB, 0b1010_1010);
-        cpu.run()
-    }
-    assert_eq!(0b1101_0101, mock.memory[0xAB]);
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    cpu.memory.mem_write_u8(0xAB, 0b1010_1010);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b1101_0101, cpu.memory.memory[0xAB]);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_tax() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     TAX
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_txa() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDX #42
     TXA
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_tay() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #42
     TAY
     STY $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_tya() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDY #42
     TYA
     STA $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(42, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42]);
 }
 
 #[test]
 fn test_txs() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"LDA #1
     PHA
     LDA #2
@@ -993,20 +951,639 @@ fn test_txs() {
     PLA
     STA $43"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(30, mock.memory[0x42]);
-    assert_eq!(10, mock.memory[0x43]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(30, cpu.memory.memory[0x42]);
+    assert_eq!(10, cpu.memory.memory[0x43]);
 }
 
 #[test]
 fn test_tsx() {
-    let mut mock = create_mock_from_script(
+    let mock = create_mock_from_script(
         r#"PHA
     TSX
     STX $42"#,
     );
-    let mut cpu = Cpu::new(&mut mock);
-    unsafe { cpu.run() }
-    assert_eq!(0xFE, mock.memory[0x42]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(0xFE, cpu.memory.memory[0x42]);
+}
+
+fn create_mock_with_handler(script: &str, vector: u16, handler: &str) -> MemoryMock {
+    let mut mock = create_mock_from_script(script);
+    let handler = asm_6502::compile(handler.to_string(), 0x9000);
+    mock.load(&handler, 0x9000);
+    mock.mem_write_u16(vector, 0x9000);
+    mock
+}
+
+#[test]
+fn test_brk() {
+    let mut mock = create_mock_from_script("BRK");
+    mock.mem_write_u16(0xFFFE, 0x9000);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(0x9000, cpu.counter);
+    assert_eq!(
+        [0b0011_0100, 0x02, 0x80],
+        cpu.memory.memory[0x01FD..=0x01FF]
+    );
+    assert!(cpu.status.is_set(register::Status::INTERRUPT_DISABLE));
+}
+
+#[test]
+fn test_irq_masked() {
+    let mock = create_mock_with_handler("INX\nBRK", 0xFFFE, "STX $42\nBRK");
+    let mut cpu = Cpu::new(mock);
+    cpu.set_irq(true);
+    run_until_brk(&mut cpu);
+    assert_eq!(0, cpu.memory.memory[0x42]);
+}
+
+#[test]
+fn test_irq_after_cli_latency() {
+    let mock = create_mock_with_handler(
+        r#"CLI
+    INX
+    INX
+    BRK"#,
+        0xFFFE,
+        r#"STX $42
+    BRK"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.set_irq(true);
+    run_until_brk(&mut cpu);
+    assert_eq!(1, cpu.memory.memory[0x42]);
+    // return address and status without B
+    assert_eq!(
+        [0b0010_0000, 0x02, 0x80],
+        cpu.memory.memory[0x01FD..=0x01FF]
+    );
+}
+
+#[test]
+fn test_irq_after_sei() {
+    let mock = create_mock_with_handler(
+        r#"SEI
+    INX
+    BRK"#,
+        0xFFFE,
+        r#"STX $42
+    BRK"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.status.remove(register::Status::INTERRUPT_DISABLE);
+    cpu.set_irq(true);
+    run_until_brk(&mut cpu);
+    assert_eq!(0, cpu.memory.memory[0x42]);
+    // the pushed status already has I set
+    assert_eq!(
+        [0b0010_0100, 0x01, 0x80],
+        cpu.memory.memory[0x01FD..=0x01FF]
+    );
+}
+
+#[test]
+fn test_taken_branch_delays_irq() {
+    let mock = create_mock_with_handler(
+        r#"CLI
+    BNE next
+next:
+    INX
+    INX
+    BRK"#,
+        0xFFFE,
+        r#"STX $42
+    BRK"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.set_irq(true);
+    run_until_brk(&mut cpu);
+    assert_eq!(1, cpu.memory.memory[0x42]);
+}
+
+#[test]
+fn test_nmi_is_edge_triggered() {
+    let mock = create_mock_with_handler(
+        r#"INX
+    INX
+    STX $42
+    BRK"#,
+        0xFFFA,
+        r#"INC $43
+    RTI"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    cpu.set_nmi(true);
+    cpu.set_nmi(true);
+    run_until_brk(&mut cpu);
+    assert_eq!(2, cpu.memory.memory[0x42]);
+    assert_eq!(1, cpu.memory.memory[0x43]);
+}
+
+#[test]
+fn test_nmi_hijacks_brk() {
+    let mock = create_mock_with_handler("BRK", 0xFFFA, "BRK");
+    let mut cpu = Cpu::new(mock);
+    cpu.nmi_pending = true;
+    run_until_brk(&mut cpu);
+    assert_eq!(0x9000, cpu.counter);
+    assert_eq!(0b0011_0100, cpu.memory.memory[0x01FD]);
+}
+
+#[test]
+fn test_reset() {
+    let mock = create_mock_from_script("BRK");
+    let mut cpu = Cpu::new(mock);
+    cpu.a = 42;
+    cpu.counter = 0x1234;
+    cpu.status = register::Status::CARRY;
+    cpu.reset();
+    assert_eq!(0x8000, cpu.counter);
+    assert_eq!(0xFC, cpu.stack_pointer);
+    assert_eq!(42, cpu.a);
+    assert_eq!(
+        register::Status::CARRY | register::Status::INTERRUPT_DISABLE,
+        cpu.status
+    );
+}
+
+fn count_cycles(script: &str, x: u8) -> u64 {
+    let mock = create_mock_from_script(script);
+    let mut cpu = Cpu::new(mock);
+    cpu.x = x;
+    run_until_brk(&mut cpu);
+    // without the BRK
+    cpu.cycles() - 7
+}
+
+#[test]
+fn test_cycles() {
+    assert_eq!(2 + 3 + 6, count_cycles("LDA #1\nSTA $10\nINC $1234", 0));
+    assert_eq!(2 + 2 + 3 + 4, count_cycles("SEC\nCLD\nPHA\nPLA", 0));
+}
+
+#[test]
+fn test_page_cross_cycles() {
+    assert_eq!(4, count_cycles("LDA $10F0,X", 0x0F));
+    assert_eq!(5, count_cycles("LDA $10F0,X", 0x10));
+    // writes and read-modify-writes always take the extra cycle
+    assert_eq!(5, count_cycles("STA $10F0,X", 0x0F));
+    assert_eq!(7, count_cycles("ASL $10F0,X", 0x10));
+}
+
+#[test]
+fn test_indirect_y_page_cross_cycles() {
+    let mut mock = create_mock_from_script("LDA ($10),Y\nLDA ($10),Y");
+    mock.memory[0x10..0x12].copy_from_slice(&[0xF0, 0x10]);
+    let mut cpu = Cpu::new(mock);
+    cpu.y = 0x10;
+    run_until_brk(&mut cpu);
+    assert_eq!(2 * 6 + 7, cpu.cycles());
+}
+
+#[test]
+fn test_branch_cycles() {
+    // not taken, taken, then taken to another page
+    assert_eq!(2, count_cycles("CLC\nBCS end\nend:", 0) - 2);
+    assert_eq!(3, count_cycles("CLC\nBCC end\nend:", 0) - 2);
+    // CLC then BCC back to $7FFF, where a BRK is waiting
+    let mock = MemoryMock::new(&[0x18, 0x90, 0xFC], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(2 + 4 + 7, cpu.cycles());
+}
+
+#[test]
+fn test_branch_to_next_instruction() {
+    for execution_mode in [ExecutionMode::Instruction, ExecutionMode::Cycle] {
+        // BNE to the offset byte itself, then JMP and JSR to the next byte
+        let mock = MemoryMock::new(&[0xD0, 0xFF, 0x4C, 0x05, 0x80, 0x20, 0x08, 0x80], 0x8000);
+        let mut cpu = Cpu::with_execution_mode(mock, execution_mode);
+        cpu.counter = 0x8000;
+        assert_eq!(3, cpu.step());
+        assert_eq!(0x8001, cpu.counter);
+        cpu.counter = 0x8002;
+        assert_eq!(3, cpu.step());
+        assert_eq!(0x8005, cpu.counter);
+        assert_eq!(6, cpu.step());
+        assert_eq!(0x8008, cpu.counter);
+    }
+}
+
+#[test]
+fn test_step_returns_cycles() {
+    let mock = create_mock_from_script("LDA #1\nSTA $1234\nBRK");
+    let mut cpu = Cpu::new(mock);
+    cpu.counter = 0x8000;
+    let cycles: Vec<u32> = (0..3).map(|_| cpu.step()).collect();
+    assert_eq!([2, 4, 7], cycles[..]);
+    assert_eq!(13, cpu.cycles());
+}
+
+#[test]
+fn test_trace_without_ppu() {
+    let mut cpu = Cpu::new(MemoryMock::new(&[0xA9, 0x05, 0xAA, 0x00], 0x8000));
+    cpu.set_tracer(Some(Tracer::ring_buffer(8)));
+    run_until_brk(&mut cpu);
+    let lines: Vec<&String> = cpu.tracer().unwrap().lines().collect();
+    assert_eq!(3, lines.len());
+    assert!(lines[0].starts_with("8000  A9 05     LDA #$05"));
+    assert!(lines[1].starts_with("8002  AA        TAX"));
+    assert!(lines[2].starts_with("8003  00        BRK"));
+    assert!(lines[1].ends_with("PPU:  0,  0 CYC:2"));
+}
+
+#[test]
+fn test_counter_wraps() {
+    let mut mock = MemoryMock::new(&[], 0xFFFF);
+    // NOP at the end of the address space, then LDA #$42 across it
+    mock.memory[0xFFFF] = 0xEA;
+    mock.memory[0x0000] = 0x42;
+    let mut cpu = Cpu::new(mock);
+    cpu.counter = 0xFFFF;
+    cpu.step();
+    assert_eq!(0x0000, cpu.counter);
+    cpu.counter = 0xFFFF;
+    cpu.memory.memory[0xFFFF] = 0xA9;
+    cpu.step();
+    assert_eq!(0x42, cpu.a);
+    assert_eq!(0x0001, cpu.counter);
+}
+
+fn run_bytes(program: &[u8], setup: impl Fn(&mut Cpu<MemoryMock>)) -> Cpu<MemoryMock> {
+    let mut cpu = Cpu::new(MemoryMock::new(program, 0x8000));
+    setup(&mut cpu);
+    run_until_brk(&mut cpu);
+    cpu
+}
+
+#[test]
+fn test_lax_and_sax() {
+    let mut mock = MemoryMock::new(&[0xA7, 0x10, 0xA2, 0b0110, 0x87, 0x20], 0x8000);
+    mock.memory[0x10] = 0b1100;
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(0b1100, cpu.a);
+    assert_eq!(0b0100, cpu.memory.memory[0x20]);
+}
+
+#[test]
+fn test_dcp_and_isc() {
+    let mut mock = MemoryMock::new(&[0xC7, 0x10, 0x38, 0xE7, 0x11], 0x8000);
+    mock.memory[0x10..0x12].copy_from_slice(&[5, 2]);
+    let mut cpu = Cpu::new(mock);
+    cpu.a = 4;
+    run_until_brk(&mut cpu);
+    assert_eq!([4, 3], cpu.memory.memory[0x10..0x12]);
+    assert_eq!(1, cpu.a);
+}
+
+#[test]
+fn test_read_modify_write_combos() {
+    // SLO, RLA, SRE and RRA on $10 to $13
+    let program = [0x07, 0x10, 0x27, 0x11, 0x47, 0x12, 0x18, 0x67, 0x13];
+    let mut mock = MemoryMock::new(&program, 0x8000);
+    mock.memory[0x10..0x14].copy_from_slice(&[0b1000_0001, 0b0100_0001, 0b0000_0110, 0b11]);
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(
+        [0b0000_0010, 0b1000_0011, 0b0000_0011, 0b0000_0001],
+        cpu.memory.memory[0x10..0x14]
+    );
+    // ((0b10 & 0b1000_0011) ^ 0b11) + 0b1 + carry out of ROR
+    assert_eq!(3, cpu.a);
+}
+
+#[test]
+fn test_immediate_combos() {
+    let cpu = run_bytes(&[0x0B, 0x80], |cpu| cpu.a = 0xFF);
+    assert_eq!(0x80, cpu.a);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+    let cpu = run_bytes(&[0x4B, 0x0F], |cpu| cpu.a = 0xFF);
+    assert_eq!(0x07, cpu.a);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+    let cpu = run_bytes(&[0x38, 0x6B, 0xC0], |cpu| cpu.a = 0xFF);
+    assert_eq!(0xE0, cpu.a);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+    assert!(cpu.status.is_unset(register::Status::OVERFLOW));
+    let cpu = run_bytes(&[0xCB, 0x02], |cpu| {
+        cpu.a = 0x0F;
+        cpu.x = 0x03;
+    });
+    assert_eq!(0x01, cpu.x);
+    assert!(cpu.status.is_set(register::Status::CARRY));
+}
+
+#[test]
+fn test_unofficial_nops() {
+    #[rustfmt::skip]
+    let program = [
+        0x1A,
+        0x80, 0xFF,
+        0x04, 0xFF,
+        0x14, 0xFF,
+        0x0C, 0xFF, 0xFF,
+        0x1C, 0xF0, 0x10,
+        0xA9, 0x01,
+    ];
+    let cpu = run_bytes(&program, |cpu| cpu.x = 0x10);
+    assert_eq!(1, cpu.a);
+    assert_eq!(2 + 2 + 3 + 4 + 4 + 5 + 2 + 7, cpu.cycles());
+}
+
+#[test]
+fn test_jam_halts_until_reset() {
+    let mock = MemoryMock::new(&[0xE8, 0x02, 0xE8], 0x8000);
+    let mut cpu = Cpu::new(mock);
+    cpu.counter = 0x8000;
+    cpu.step();
+    cpu.step();
+    assert!(cpu.is_halted());
+    assert_eq!(1, cpu.step());
+    assert_eq!((1, 0x8001), (cpu.x, cpu.counter));
+    cpu.reset();
+    assert!(!cpu.is_halted());
+}
+
+#[test]
+fn test_unstable_opcodes() {
+    // XAA #$FF
+    let cpu = run_bytes(&[0x8B, 0xFF], |cpu| {
+        cpu.a = 0x01;
+        cpu.x = 0x0F;
+    });
+    assert_eq!(0x0F & (0x01 | UNSTABLE_MAGIC), cpu.a);
+    let cpu = run_bytes(&[0x8B, 0xFF], |cpu| {
+        cpu.set_unstable_opcodes(UnstableOpcodes::Halt);
+    });
+    assert!(cpu.is_halted());
+}
+
+#[test]
+fn test_shy_page_cross() {
+    // SHY $12F0,X
+    let mock = run_bytes(&[0x9C, 0xF0, 0x12], |cpu| {
+        cpu.y = 0xFF;
+        cpu.x = 0x01;
+    })
+    .memory;
+    assert_eq!(0x13, mock.memory[0x12F1]);
+    // the stored value becomes the high byte of the address
+    let mock = run_bytes(&[0x9C, 0xF0, 0x12], |cpu| {
+        cpu.y = 0x0F;
+        cpu.x = 0x10;
+    })
+    .memory;
+    assert_eq!(0x03, mock.memory[0x0300]);
+}
+
+#[test]
+fn test_jsr_rts() {
+    let mock = create_mock_from_script(
+        r#"JSR sub
+    STA $42
+    BRK
+sub:
+    LDA $01FE
+    STA $40
+    LDA $01FF
+    STA $41
+    LDA #42
+    RTS"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(42, cpu.memory.memory[0x42]);
+    // the return address pushed is the last byte of JSR
+    assert_eq!([0x02, 0x80], cpu.memory.memory[0x40..0x42]);
+}
+
+#[test]
+fn test_bit_flags() {
+    let mut mock = create_mock_from_script(
+        r#"LDA #$01
+    BIT $10"#,
+    );
+    mock.memory[0x10] = 0b1100_0000;
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert!(cpu.status.contains(register::Status::NEGATIVE));
+    assert!(cpu.status.contains(register::Status::OVERFLOW));
+    assert!(cpu.status.contains(register::Status::ZERO));
+}
+
+#[test]
+fn test_cmp_equal_sets_carry() {
+    let mock = create_mock_from_script(
+        r#"LDA #42
+    CMP #42"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert!(cpu.status.contains(register::Status::CARRY));
+    assert!(cpu.status.contains(register::Status::ZERO));
+}
+
+#[test]
+fn test_transfer_and_decrement_flags() {
+    let mock = create_mock_from_script(
+        r#"LDX #$01
+    DEX
+    PHP
+    LDA #$80
+    TAY
+    PHP"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    let pushed = |addr: usize| register::Status::from_bits_truncate(cpu.memory.memory[addr]);
+    assert!(pushed(0x01FF).contains(register::Status::ZERO));
+    assert!(pushed(0x01FE).contains(register::Status::NEGATIVE));
+    assert!(!pushed(0x01FE).contains(register::Status::ZERO));
+}
+
+#[test]
+fn test_php_plp_break_flag() {
+    let mock = create_mock_from_script(
+        r#"PHP
+    PLA
+    STA $40
+    PHA
+    PLP"#,
+    );
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!(
+        register::Status::BREAK | register::Status::UNUSED,
+        register::Status::from_bits_truncate(cpu.memory.memory[0x40])
+            & (register::Status::BREAK | register::Status::UNUSED)
+    );
+    assert!(!cpu.status.contains(register::Status::BREAK));
+}
+
+#[test]
+fn test_jmp_indirect_page_wrap() {
+    #[rustfmt::skip]
+    let program = [
+        0x6C, 0xFF, 0x10,   // JMP ($10FF)
+    ];
+    let mut mock = MemoryMock::new(&program, 0x8000);
+    mock.memory[0x10FF] = 0x34;
+    mock.memory[0x1000] = 0x12;
+    mock.memory[0x1100] = 0x56;
+    let mut cpu = Cpu::new(mock);
+    cpu.counter = 0x8000;
+    cpu.step();
+    assert_eq!(0x1234, cpu.counter);
+}
+
+#[test]
+fn test_zero_page_pointer_wrap() {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x01,         // LDX #$01
+        0xA1, 0xFE,         // LDA ($FE,X)
+        0x85, 0x40,         // STA $40
+        0xA0, 0x01,         // LDY #$01
+        0xB1, 0xFF,         // LDA ($FF),Y
+        0x85, 0x41,         // STA $41
+    ];
+    let mut mock = MemoryMock::new(&program, 0x8000);
+    mock.memory[0xFF] = 0x00;
+    mock.memory[0x00] = 0x03;
+    mock.memory[0x0100] = 0x04;
+    mock.memory[0x0300] = 42;
+    mock.memory[0x0301] = 43;
+    let mut cpu = Cpu::new(mock);
+    run_until_brk(&mut cpu);
+    assert_eq!([42, 43], cpu.memory.memory[0x40..0x42]);
+}
+
+fn run_decimal_script(variant: Variant, script: &str) -> (u8, register::Status) {
+    let mock = create_mock_from_script(script);
+    let mut cpu = Cpu::new(mock);
+    cpu.set_variant(variant);
+    run_until_brk(&mut cpu);
+    (cpu.a, cpu.status)
+}
+
+#[test]
+fn test_adc_decimal() {
+    let script = r#"SED
+    CLC
+    LDA #$19
+    ADC #$28"#;
+    assert_eq!(0x47, run_decimal_script(Variant::Nmos6502, script).0);
+    assert_eq!(0x41, run_decimal_script(Variant::Ricoh2A03, script).0);
+}
+
+#[test]
+fn test_adc_decimal_flags() {
+    let (a, status) = run_decimal_script(
+        Variant::Nmos6502,
+        r#"SED
+    SEC
+    LDA #$99
+    ADC #$00"#,
+    );
+    assert_eq!(0x00, a);
+    assert!(status.contains(register::Status::CARRY));
+    // Z comes from the binary sum $9A
+    assert!(!status.contains(register::Status::ZERO));
+    assert!(status.contains(register::Status::NEGATIVE));
+}
+
+#[test]
+fn test_sbc_decimal() {
+    let (a, status) = run_decimal_script(
+        Variant::Nmos6502,
+        r#"SED
+    SEC
+    LDA #$42
+    SBC #$13"#,
+    );
+    assert_eq!(0x29, a);
+    assert!(status.contains(register::Status::CARRY));
+    let (a, status) = run_decimal_script(
+        Variant::Nmos6502,
+        r#"SED
+    SEC
+    LDA #$10
+    SBC #$20"#,
+    );
+    assert_eq!(0x90, a);
+    assert!(!status.contains(register::Status::CARRY));
+}
+
+/// Same instruction from the same random state in both execution modes, the
+/// cycle mode counts one cycle per bus access.
+#[test]
+fn test_cycle_mode_matches_instruction_mode() {
+    let mut seed: u32 = 42;
+    let mut random = move || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (seed >> 16) as u8
+    };
+    for opcode in 0..=u8::MAX {
+        for _ in 0..8 {
+            let mut mock = MemoryMock::new(&[], 0x0000);
+            mock.memory.iter_mut().for_each(|byte| *byte = random());
+            mock.memory[0x8000] = opcode;
+            let mut cycle_mock = MemoryMock::new(&[], 0x0000);
+            cycle_mock.memory = mock.memory;
+            let registers = [random(), random(), random(), random(), random()];
+
+            let mut cpu = Cpu::new(mock);
+            let mut cycle_cpu = Cpu::with_execution_mode(cycle_mock, ExecutionMode::Cycle);
+            for cpu in [&mut cpu, &mut cycle_cpu] {
+                cpu.counter = 0x8000;
+                [cpu.a, cpu.x, cpu.y, cpu.stack_pointer] =
+                    [registers[0], registers[1], registers[2], registers[3]];
+                cpu.status = register::Status::from_bits_truncate(registers[4]);
+            }
+            let cycles = cpu.step();
+            let cycle_mode_cycles = cycle_cpu.step();
+            assert_eq!(cycles, cycle_mode_cycles, "opcode {opcode:#04X}");
+            assert_eq!(
+                (
+                    cpu.counter,
+                    cpu.a,
+                    cpu.x,
+                    cpu.y,
+                    cpu.stack_pointer,
+                    cpu.status
+                ),
+                (
+                    cycle_cpu.counter,
+                    cycle_cpu.a,
+                    cycle_cpu.x,
+                    cycle_cpu.y,
+                    cycle_cpu.stack_pointer,
+                    cycle_cpu.status
+                ),
+                "opcode {opcode:#04X}"
+            );
+            assert!(
+                cpu.memory.memory == cycle_cpu.memory.memory,
+                "opcode {opcode:#04X}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_cycle_mode_interrupt_sequence() {
+    let mock = create_mock_from_script(
+        r#"CLI
+    NOP"#,
+    );
+    let mut cpu = Cpu::with_execution_mode(mock, ExecutionMode::Cycle);
+    cpu.counter = 0x8000;
+    cpu.set_irq(true);
+    let cycles: Vec<u32> = (0..3).map(|_| cpu.step()).collect();
+    // CLI, NOP then the IRQ sequence
+    assert_eq!(vec![2, 2, 7], cycles);
+    assert_eq!(0, cpu.counter);
 }
@@ -15,12 +15,12 @@ bitflags! {
     }
 }
 
+#[derive(Clone)]
 pub struct Joypad {
     address: u16,
     is_strobe_on: bool,
     current_button_mask: Button,
     button_status: Button,
-    memory: *mut u8,
 }
 
 impl Joypad {
@@ -30,7 +30,6 @@ impl Joypad {
             is_strobe_on: false,
             current_button_mask: Button::A,
             button_status: Button::from_bits_truncate(0),
-            memory: std::ptr::null_mut(),
         }
     }
 
@@ -48,30 +47,34 @@ impl Device for Joypad {
         usize::from(self.address)..usize::from(self.address + 1)
     }
 
-    fn map(&mut self, memory: &mut [u8]) {
-        self.memory = &mut memory[0]
-    }
-
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_read(&mut self) {
-        self.is_strobe_on = *self.memory & 1 == 1;
+    /// Strobing reloads the shift register with the buttons.
+    fn write(&mut self, _addr: u16, value: u8) {
+        self.is_strobe_on = value & 1 == 1;
         if self.is_strobe_on {
             self.current_button_mask = Button::A
         }
     }
 
-    /// # Safety
-    /// Make sure that `memory` ptr is valid
-    unsafe fn mem_write(&mut self) {
-        if self.current_button_mask.is_empty() {
-            *self.memory = 1;
-            return;
-        }
-        *self.memory = u8::from(self.button_status.contains(self.current_button_mask));
-        if !self.is_strobe_on {
+    /// Returns the next button and shifts, the official controllers return 1
+    /// after the 8 buttons.
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        if !self.is_strobe_on && !self.current_button_mask.is_empty() {
             self.current_button_mask.bits <<= 1;
         }
+        value
+    }
+
+    /// D1-D4 read as 0 on a console with nothing in the expansion port.
+    fn driven_bits(&self, _addr: u16) -> u8 {
+        0b0001_1111
+    }
+
+    fn peek(&self, _addr: u16) -> u8 {
+        if self.current_button_mask.is_empty() {
+            return 1;
+        }
+        u8::from(self.button_status.contains(self.current_button_mask))
     }
 }
 
@@ -80,87 +83,65 @@ mod tests {
 
     #[test]
     fn test_press_button_a() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
-        unsafe {
-            joypad.mem_write();
-        }
-        assert_eq!(joypad_byte[0], 1);
+        assert_eq!(joypad.read(0x4016), 1);
     }
 
     #[test]
     fn test_release_button_a() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
         joypad.release(Button::A);
-        unsafe {
-            joypad.mem_write();
-        }
-        assert_eq!(joypad_byte[0], 0);
+        assert_eq!(joypad.read(0x4016), 0);
     }
 
     #[test]
     fn test_button_index_reset() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
-        unsafe {
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 1);
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 0);
-
-            std::ptr::write_volatile(&mut joypad_byte[0], 1);
-            joypad.mem_read();
-            std::ptr::write_volatile(&mut joypad_byte[0], 0);
-            joypad.mem_read();
-
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 1);
-            joypad.mem_write();
-            assert_eq!(joypad_byte[0], 0);
-        }
+        assert_eq!(joypad.read(0x4016), 1);
+        assert_eq!(joypad.read(0x4016), 0);
+
+        joypad.write(0x4016, 1);
+        joypad.write(0x4016, 0);
+
+        assert_eq!(joypad.read(0x4016), 1);
+        assert_eq!(joypad.read(0x4016), 0);
     }
 
     #[test]
     fn test_reading_when_strobe_off() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
         joypad.press(Button::A);
         joypad.press(Button::SELECT);
         joypad.press(Button::UP);
 
         let expected_results = [1, 0, 1, 0, 1, 0, 0, 0, 1, 1, 1];
         for result in expected_results {
-            unsafe {
-                joypad.mem_write();
-            }
-            assert_eq!(joypad_byte[0], result);
+            assert_eq!(joypad.read(0x4016), result);
         }
     }
 
     #[test]
     fn test_reading_when_strobe_on() {
-        let mut joypad_byte = [0; 1];
         let mut joypad = Joypad::new(0x4016);
-        joypad.map(&mut joypad_byte);
-        unsafe {
-            std::ptr::write_volatile(&mut joypad_byte[0], 1);
-            joypad.mem_read();
-        }
+        joypad.write(0x4016, 1);
         joypad.press(Button::A);
 
         for _ in 0..3 {
-            unsafe {
-                joypad.mem_write();
-            }
-            assert_eq!(joypad_byte[0], 1);
+            assert_eq!(joypad.read(0x4016), 1);
+        }
+    }
+
+    #[test]
+    fn test_peek_does_not_shift() {
+        let mut joypad = Joypad::new(0x4016);
+        joypad.press(Button::A);
+        for _ in 0..3 {
+            assert_eq!(joypad.peek(0x4016), 1);
         }
+        assert_eq!(joypad.read(0x4016), 1);
+        assert_eq!(joypad.peek(0x4016), 0);
     }
 }
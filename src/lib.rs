@@ -1,45 +1,274 @@
+#![forbid(unsafe_code)]
+pub mod apu;
 mod bus;
+pub mod cartridge;
 pub mod cpu;
 mod joypad;
+pub mod mapper;
+pub mod memory_map;
+pub mod ppu;
+pub mod trace;
 pub mod traits;
 use bus::Bus;
-use cpu::Cpu;
+use cartridge::{Cartridge, Timing};
+use cpu::{Cpu, ExecutionMode, UnstableOpcodes};
 use joypad::Joypad;
-use std::{marker::PhantomPinned, pin::Pin, ptr};
+use memory_map::MemoryMap;
+use trace::Tracer;
+use traits::Memory;
 
+/// A console : the CPU owns the bus, which owns the other chips and the
+/// cartridge. Cloning it makes a save state, without the tracer.
+#[derive(Clone)]
 pub struct Nes {
-    memory: [u8; 0xFFFF],
-    joypad_1: Joypad,
-    joypad_2: Joypad,
-    bus: Bus,
-    cpu: Cpu,
-    _pin: PhantomPinned,
+    cpu: Cpu<Bus>,
+    timing: Timing,
 }
 
 impl Nes {
-    pub fn new() -> Pin<Box<Self>> {
-        let nes = Self {
-            memory: [0; 0xFFFF],
-            joypad_1: Joypad::new(0x4016),
-            joypad_2: Joypad::new(0x4017),
-            bus: Bus::new(),
-            cpu: Cpu::new(ptr::null_mut::<Bus>()),
-            _pin: PhantomPinned,
-        };
-
-        let mut pinned_boxed_nes = Box::pin(nes);
-        unsafe {
-            pinned_boxed_nes.map_devices();
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_execution_mode(ExecutionMode::Instruction)
+    }
+
+    /// The cycle mode runs the PPU between the bus accesses of each
+    /// instruction, at the expense of speed.
+    pub fn with_execution_mode(execution_mode: ExecutionMode) -> Self {
+        Self::with_memory_map(MemoryMap::nes(), execution_mode)
+    }
+
+    /// A console whose CPU bus is laid out by `memory_map` instead of the
+    /// NES one.
+    pub fn with_memory_map(memory_map: MemoryMap, execution_mode: ExecutionMode) -> Self {
+        let mut bus = Bus::with_memory_map(memory_map);
+        bus.add_device(Box::new(Joypad::new(0x4016)));
+        bus.add_device(Box::new(Joypad::new(0x4017)));
+        Self {
+            cpu: Cpu::with_execution_mode(bus, execution_mode),
+            timing: Timing::Ntsc,
+        }
+    }
+
+    pub fn from_rom(rom: &[u8]) -> Result<Self, cartridge::Error> {
+        let cartridge = Cartridge::from_bytes(rom)?;
+        let mut nes = Self::new();
+        nes.insert_cartridge(cartridge)?;
+        Ok(nes)
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) -> Result<(), cartridge::Error> {
+        let timing = cartridge.header.timing;
+        let bus = self.cpu.memory_mut();
+        bus.insert_mapper(mapper::create(cartridge)?);
+        bus.set_timing(timing);
+        self.timing = timing;
+        let cycles = self.cpu.cycles();
+        self.cpu.power_up();
+        self.tick_devices((self.cpu.cycles() - cycles) as u32);
+        Ok(())
+    }
+
+    /// Pressing the reset button.
+    pub fn reset(&mut self) {
+        let cycles = self.cpu.cycles();
+        self.cpu.memory_mut().apu_mut().reset();
+        self.cpu.reset();
+        self.tick_devices((self.cpu.cycles() - cycles) as u32);
+    }
+
+    pub fn set_unstable_opcodes(&mut self, behaviour: UnstableOpcodes) {
+        self.cpu.set_unstable_opcodes(behaviour);
+    }
+
+    /// Traces every instruction executed from now on, `None` stops tracing.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.set_tracer(tracer);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.cpu.tracer()
+    }
+
+    /// Disassembles the instruction at `addr` as the CPU sees it now, with the
+    /// banks the mapper has selected, and returns its text and length.
+    pub fn disassemble(&self, addr: u16) -> (String, u8) {
+        let bus = self.cpu.memory();
+        cpu::disassemble(|addr| bus.peek(addr), addr)
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Runs whole instructions until at least `cycles` CPU cycles elapsed.
+    pub fn run_for_cycles(&mut self, cycles: u64) {
+        let target = self.cpu.cycles() + cycles;
+        while self.cpu.cycles() < target {
+            self.step();
+        }
+    }
+
+    /// Runs until the PPU enters the next VBlank, when a frame is complete.
+    pub fn run_frame(&mut self) {
+        let mut was_in_vblank = self.ppu().is_in_vblank();
+        loop {
+            self.step();
+            let is_in_vblank = self.ppu().is_in_vblank();
+            if is_in_vblank && !was_in_vblank {
+                break;
+            }
+            was_in_vblank = is_in_vblank;
+        }
+    }
+
+    fn step(&mut self) {
+        let cycles = self.cpu.step();
+        self.tick_devices(cycles);
+    }
+
+    /// In cycle mode the CPU already ticked the bus on each of its cycles.
+    fn tick_devices(&mut self, cycles: u32) {
+        if self.cpu.execution_mode() == ExecutionMode::Cycle {
+            return;
+        }
+        let bus = self.cpu.memory_mut();
+        bus.tick_devices(cycles);
+        let stall_cycles = bus.take_stall_cycles();
+        self.cpu.stall(stall_cycles);
+        let nmi = self.ppu().nmi();
+        self.cpu.set_nmi(nmi);
+    }
+
+    fn ppu(&self) -> &ppu::Ppu {
+        self.cpu.memory().ppu()
+    }
+
+    /// Last frame drawn by the PPU, as `FRAME_WIDTH * FRAME_HEIGHT` indexes
+    /// into `ppu::palette::SYSTEM_PALETTE`.
+    pub fn frame_buffer(&self) -> &[u8] {
+        self.ppu().frame_buffer()
+    }
+
+    /// Audio samples between 0.0 and 1.0 produced since the last call.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.memory_mut().apu_mut().take_samples()
+    }
+
+    /// 44100 samples per second by default.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.memory_mut().apu_mut().set_sample_rate(sample_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// NROM with an NMI handler counting frames at $10
+    fn create_test_rom() -> Vec<u8> {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0];
+        rom.resize(16, 0);
+        let mut prg = vec![0; cartridge::PRG_ROM_BANK_SIZE];
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x80,         // LDA #$80
+            0x8D, 0x00, 0x20,   // STA $2000
+            0x4C, 0x05, 0x80,   // JMP $8005
+            0xE6, 0x10,         // INC $10
+            0x40,               // RTI
+        ];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x3FFA..].copy_from_slice(&[0x08, 0x80, 0x00, 0x80, 0x00, 0x80]);
+        rom.extend(prg);
+        rom.extend(vec![0; cartridge::CHR_ROM_BANK_SIZE]);
+        rom
+    }
+
+    #[test]
+    fn test_run_for_cycles() {
+        let mut nes = Nes::from_rom(&create_test_rom()).unwrap();
+        let start = nes.cpu.cycles();
+        nes.run_for_cycles(100);
+        assert!((100..103).contains(&(nes.cpu.cycles() - start)));
+        // the PPU also runs during the reset sequence
+        assert_eq!(
+            nes.cpu.cycles() * 3,
+            u64::from(nes.ppu().scanline()) * 341 + u64::from(nes.ppu().dot())
+        );
+    }
+
+    #[test]
+    fn test_run_for_cycles_in_cycle_mode() {
+        let mut nes = Nes::with_execution_mode(ExecutionMode::Cycle);
+        nes.insert_cartridge(Cartridge::from_bytes(&create_test_rom()).unwrap())
+            .unwrap();
+        nes.run_for_cycles(100);
+        assert_eq!(
+            nes.cpu.cycles() * 3,
+            u64::from(nes.ppu().scanline()) * 341 + u64::from(nes.ppu().dot())
+        );
+    }
+
+    #[test]
+    fn test_run_frame_in_cycle_mode() {
+        let mut nes = Nes::with_execution_mode(ExecutionMode::Cycle);
+        nes.insert_cartridge(Cartridge::from_bytes(&create_test_rom()).unwrap())
+            .unwrap();
+        for _ in 0..3 {
+            nes.run_frame();
         }
-        pinned_boxed_nes
+        assert_eq!((2, 241), (nes.ppu().frame(), nes.ppu().scanline()));
+        assert_eq!(2, nes.cpu.memory().peek(0x10));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut nes = Nes::from_rom(&create_test_rom()).unwrap();
+        nes.set_tracer(Some(Tracer::ring_buffer(4)));
+        nes.run_for_cycles(8);
+        let lines: Vec<&String> = nes.tracer().unwrap().lines().collect();
+        assert_eq!(
+            vec![
+                "8000  A9 80     LDA #$80                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+                "8002  8D 00 20  STA $2000                       A:80 X:00 Y:00 P:A4 SP:FD PPU:  0, 27 CYC:9",
+                "8005  4C 05 80  JMP $8005                       A:80 X:00 Y:00 P:A4 SP:FD PPU:  0, 39 CYC:13",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let nes = Nes::from_rom(&create_test_rom()).unwrap();
+        assert_eq!(("STA $2000".to_string(), 3), nes.disassemble(0x8002));
+        // 16KB of PRG are mirrored in $C000-$FFFF
+        assert_eq!(("JMP $8005".to_string(), 3), nes.disassemble(0xC005));
+    }
+
+    #[test]
+    fn test_clone_is_a_save_state() {
+        let mut nes = Nes::from_rom(&create_test_rom()).unwrap();
+        nes.run_frame();
+        let mut state = nes.clone();
+        nes.run_frame();
+        nes.run_frame();
+        assert_eq!(0, state.cpu.memory().peek(0x10));
+        state.run_frame();
+        state.run_frame();
+        assert_eq!(nes.cpu.registers(), state.cpu.registers());
+        assert_eq!(nes.cpu.cycles(), state.cpu.cycles());
+        assert_eq!(2, state.cpu.memory().peek(0x10));
     }
 
-    unsafe fn map_devices(self: &mut Pin<Box<Self>>) {
-        let pinned_nes_ref: Pin<&mut Self> = Pin::as_mut(self);
-        let nes_ref: &mut Self = Pin::get_unchecked_mut(pinned_nes_ref);
-        nes_ref.bus.map(
-            &mut nes_ref.memory,
-            &[&mut nes_ref.joypad_1, &mut nes_ref.joypad_2],
-        )
+    #[test]
+    fn test_run_frame() {
+        let mut nes = Nes::from_rom(&create_test_rom()).unwrap();
+        nes.run_frame();
+        assert_eq!((0, 241), (nes.ppu().frame(), nes.ppu().scanline()));
+        assert_eq!(0, nes.cpu.memory().peek(0x10));
+        nes.run_frame();
+        nes.run_frame();
+        assert_eq!((2, 241), (nes.ppu().frame(), nes.ppu().scanline()));
+        assert_eq!(2, nes.cpu.memory().peek(0x10));
     }
 }
@@ -1,10 +1,14 @@
 use gloo_render::AnimationFrame;
+use nes_emu::ppu::{palette::SYSTEM_PALETTE, FRAME_HEIGHT, FRAME_WIDTH};
 use nes_emu::Nes;
-use std::pin::Pin;
-use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
 use yew::{html, html::Scope, Component, Context, Html, NodeRef};
 
+const FRAME_DURATION_MS: f64 = 1000.0 / 60.0;
+/// Frames are dropped after a pause instead of being caught up
+const MAX_PENDING_TIME_MS: f64 = 4.0 * FRAME_DURATION_MS;
+
 pub enum Msg {
     Render { timestamp: f64 },
 }
@@ -13,7 +17,10 @@ pub struct App {
     canvas_ref: NodeRef,
     rendering_context: Option<CanvasRenderingContext2d>,
     _animation_frame: Option<AnimationFrame>,
-    nes: Pin<Box<Nes>>,
+    nes: Nes,
+    pixels: Vec<u8>,
+    last_timestamp: Option<f64>,
+    pending_time: f64,
 }
 
 impl Component for App {
@@ -26,6 +33,9 @@ impl Component for App {
             rendering_context: None,
             _animation_frame: None,
             nes: Nes::new(),
+            pixels: vec![0xFF; FRAME_WIDTH * FRAME_HEIGHT * 4],
+            last_timestamp: None,
+            pending_time: 0.0,
         }
     }
 
@@ -43,7 +53,11 @@ impl Component for App {
         html! {
             <div>
                 <h1>{ "NES Emulator" }</h1>
-                <canvas ref={ self.canvas_ref.clone() }/>
+                <canvas
+                    ref={ self.canvas_ref.clone() }
+                    width={ FRAME_WIDTH.to_string() }
+                    height={ FRAME_HEIGHT.to_string() }
+                />
             </div>
         }
     }
@@ -67,10 +81,32 @@ impl Component for App {
 }
 
 impl App {
+    /// Emulates as many frames as the elapsed time requires, whatever the
+    /// refresh rate of the display.
     fn render_frame(&mut self, timestamp: f64) {
+        let elapsed = timestamp - self.last_timestamp.unwrap_or(timestamp);
+        self.last_timestamp = Some(timestamp);
+        self.pending_time = (self.pending_time + elapsed).min(MAX_PENDING_TIME_MS);
+        while self.pending_time >= FRAME_DURATION_MS {
+            self.nes.run_frame();
+            self.pending_time -= FRAME_DURATION_MS;
+        }
+
         let rendering_context = self.rendering_context.as_ref().unwrap();
 
-        rendering_context.fill_rect(10.0, 10.0, 200.0, 10.0);
+        for (pixel, &color) in self.pixels.chunks_exact_mut(4).zip(self.nes.frame_buffer()) {
+            let (r, g, b) = SYSTEM_PALETTE[usize::from(color)];
+            pixel[..3].copy_from_slice(&[r, g, b]);
+        }
+        let image_data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&self.pixels),
+            FRAME_WIDTH as u32,
+            FRAME_HEIGHT as u32,
+        )
+        .unwrap();
+        rendering_context
+            .put_image_data(&image_data, 0.0, 0.0)
+            .unwrap();
     }
 
     fn request_animation_frame(&mut self, link: Scope<Self>) {
@@ -0,0 +1,79 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring, CHR_ROM_BANK_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+//https://www.nesdev.org/wiki/AxROM
+#[derive(Clone)]
+pub struct Axrom {
+    cartridge: Cartridge,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self {
+            cartridge,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn peek(&self, addr: u16) -> u8 {
+        if addr >= super::PRG_ROM_START {
+            super::banked_read(&self.cartridge.prg_rom, PRG_BANK_SIZE, self.prg_bank, addr)
+        } else {
+            0
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= super::PRG_ROM_START {
+            self.prg_bank = usize::from(data & 0b0000_0111);
+            self.mirroring = if data & 0b0001_0000 == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        super::read_chr(&self.cartridge, CHR_ROM_BANK_SIZE, 0, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        super::write_chr(&mut self.cartridge, CHR_ROM_BANK_SIZE, 0, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::create_test_cartridge;
+
+    #[test]
+    fn test_axrom_prg_bank_switching() {
+        let mut axrom = Axrom::new(create_test_cartridge(7, 8, 0));
+        assert_eq!(0, axrom.cpu_read(0x8000));
+        assert_eq!(1, axrom.cpu_read(0xC000));
+        axrom.cpu_write(0x8000, 2);
+        assert_eq!(4, axrom.cpu_read(0x8000));
+        assert_eq!(5, axrom.cpu_read(0xFFFF));
+    }
+
+    #[test]
+    fn test_axrom_single_screen_mirroring() {
+        let mut axrom = Axrom::new(create_test_cartridge(7, 2, 0));
+        assert_eq!(Mirroring::SingleScreenLower, axrom.mirroring());
+        axrom.cpu_write(0x8000, 0b0001_0000);
+        assert_eq!(Mirroring::SingleScreenUpper, axrom.mirroring());
+    }
+}
@@ -0,0 +1,67 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring, CHR_ROM_BANK_SIZE};
+
+//https://www.nesdev.org/wiki/CNROM
+#[derive(Clone)]
+pub struct Cnrom {
+    cartridge: Cartridge,
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self {
+            cartridge,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn peek(&self, addr: u16) -> u8 {
+        if addr >= super::PRG_ROM_START {
+            super::banked_read(&self.cartridge.prg_rom, 0x8000, 0, addr)
+        } else {
+            0
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= super::PRG_ROM_START {
+            self.chr_bank = usize::from(data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        super::read_chr(&self.cartridge, CHR_ROM_BANK_SIZE, self.chr_bank, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        super::write_chr(
+            &mut self.cartridge,
+            CHR_ROM_BANK_SIZE,
+            self.chr_bank,
+            addr,
+            data,
+        )
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.cartridge.header.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::create_test_cartridge;
+
+    #[test]
+    fn test_cnrom_chr_bank_switching() {
+        let mut cnrom = Cnrom::new(create_test_cartridge(3, 2, 4));
+        assert_eq!(0, cnrom.ppu_read(0x0000));
+        cnrom.cpu_write(0xFFFF, 3);
+        assert_eq!(3, cnrom.ppu_read(0x1FFF));
+        assert_eq!(1, cnrom.cpu_read(0xC000));
+    }
+}
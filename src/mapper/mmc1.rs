@@ -0,0 +1,412 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Format, Header, Mirroring, PRG_ROM_BANK_SIZE};
+
+const SHIFT_REGISTER_RESET: u8 = 0b1000_0000;
+const SHIFT_REGISTER_WRITES: u8 = 5;
+const CONTROL_POWER_ON: u8 = 0b0_1100;
+const CHR_BANK_SIZE: usize = 0x1000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+const OUTER_PRG_BANK_SIZE: usize = 0x4_0000;
+const SEROM_SUBMAPPER: u8 = 5;
+const SNROM_CHR_RAM_SIZE: usize = 0x2000;
+
+/// The SxROM boards reuse the CHR bank lines for PRG-RAM and PRG-ROM banking.
+//https://www.nesdev.org/wiki/MMC1#SxROM_connection_variants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Board {
+    Generic,
+    /// CHR A16 disables PRG-RAM
+    Snrom,
+    /// CHR A15 selects the 8KB PRG-RAM bank
+    Sorom,
+    /// CHR A16 selects the 256KB PRG-ROM bank
+    Surom,
+    /// CHR A16 selects the 256KB PRG-ROM bank, CHR A14-A15 the PRG-RAM bank
+    Sxrom,
+    /// 32KB of PRG-ROM that can't be switched
+    Serom,
+}
+
+impl Board {
+    /// iNES headers can't tell those boards apart, the PRG-ROM size and the
+    /// battery are the only hints left. NES 2.0 RAM sizes and submapper are
+    /// authoritative.
+    pub fn from_header(header: &Header) -> Self {
+        let prg_ram_size = header.prg_ram_size + header.prg_nvram_size;
+        if header.submapper == SEROM_SUBMAPPER {
+            Board::Serom
+        } else if prg_ram_size == 4 * PRG_RAM_BANK_SIZE {
+            Board::Sxrom
+        } else if prg_ram_size == 2 * PRG_RAM_BANK_SIZE {
+            Board::Sorom
+        } else if header.prg_rom_size > OUTER_PRG_BANK_SIZE {
+            Board::Surom
+        } else if Self::has_snrom_ram(header, prg_ram_size) {
+            Board::Snrom
+        } else {
+            Board::Generic
+        }
+    }
+
+    /// SNROM has 8KB of CHR-RAM and 8KB of PRG-RAM, SGROM the same CHR-RAM
+    /// without PRG-RAM. iNES headers always declare PRG-RAM, only its battery
+    /// proves it's there.
+    fn has_snrom_ram(header: &Header, prg_ram_size: usize) -> bool {
+        let has_prg_ram = match header.format {
+            Format::INes => header.has_battery,
+            Format::Nes2 => prg_ram_size == PRG_RAM_BANK_SIZE,
+        };
+        header.chr_rom_size == 0
+            && header.chr_ram_size + header.chr_nvram_size == SNROM_CHR_RAM_SIZE
+            && has_prg_ram
+    }
+}
+
+//https://www.nesdev.org/wiki/MMC1
+#[derive(Clone)]
+pub struct Mmc1 {
+    cartridge: Cartridge,
+    board: Board,
+    shift_register: u8,
+    write_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    last_access_was_write: bool,
+    last_chr_bank_was_1: bool,
+}
+
+impl Mmc1 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self {
+            board: Board::from_header(&cartridge.header),
+            cartridge,
+            shift_register: 0,
+            write_count: 0,
+            control: CONTROL_POWER_ON,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            last_access_was_write: false,
+            last_chr_bank_was_1: false,
+        }
+    }
+
+    fn write_shift_register(&mut self, addr: u16, data: u8) {
+        if data & SHIFT_REGISTER_RESET != 0 {
+            self.shift_register = 0;
+            self.write_count = 0;
+            self.control |= CONTROL_POWER_ON;
+            return;
+        }
+        self.shift_register |= (data & 1) << self.write_count;
+        self.write_count += 1;
+        if self.write_count == SHIFT_REGISTER_WRITES {
+            let value = self.shift_register;
+            match addr {
+                0x8000..=0x9FFF => self.control = value,
+                0xA000..=0xBFFF => self.chr_bank_0 = value,
+                0xC000..=0xDFFF => self.chr_bank_1 = value,
+                _ => self.prg_bank = value,
+            }
+            self.shift_register = 0;
+            self.write_count = 0;
+        }
+    }
+
+    fn prg_mode(&self) -> u8 {
+        (self.control >> 2) & 0b11
+    }
+
+    fn is_chr_4kb_mode(&self) -> bool {
+        self.control & 0b1_0000 != 0
+    }
+
+    /// The CHR bank register currently driving the CHR A12-A16 lines, the
+    /// SxROM boards use the upper ones for something else.
+    fn active_chr_bank(&self) -> u8 {
+        if self.is_chr_4kb_mode() && self.last_chr_bank_was_1 {
+            self.chr_bank_1
+        } else {
+            self.chr_bank_0
+        }
+    }
+
+    fn prg_rom_bank(&self, addr: u16) -> usize {
+        let outer_bank = match self.board {
+            Board::Surom | Board::Sxrom => usize::from(self.active_chr_bank() & 0b1_0000),
+            _ => 0,
+        };
+        let bank = usize::from(self.prg_bank & 0b0_1111);
+        let last_bank = 0b0_1111;
+        let inner_bank = match (self.prg_mode(), addr) {
+            (0 | 1, 0x8000..=0xBFFF) => bank & !1,
+            (0 | 1, _) => bank | 1,
+            (2, 0x8000..=0xBFFF) => 0,
+            (2, _) => bank,
+            (_, 0x8000..=0xBFFF) => bank,
+            (_, _) => last_bank,
+        };
+        outer_bank | inner_bank
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        let chip_enabled = self.prg_bank & 0b1_0000 == 0;
+        match self.board {
+            Board::Snrom => chip_enabled && self.active_chr_bank() & 0b1_0000 == 0,
+            _ => chip_enabled,
+        }
+    }
+
+    fn prg_ram_bank(&self) -> usize {
+        match self.board {
+            Board::Sorom => usize::from(self.active_chr_bank() >> 3 & 0b1),
+            Board::Sxrom => usize::from(self.active_chr_bank() >> 2 & 0b11),
+            _ => 0,
+        }
+    }
+
+    fn chr_bank(&mut self, addr: u16) -> usize {
+        self.last_chr_bank_was_1 = addr & 0x1000 != 0;
+        if self.is_chr_4kb_mode() {
+            let bank = if self.last_chr_bank_was_1 {
+                self.chr_bank_1
+            } else {
+                self.chr_bank_0
+            };
+            usize::from(bank)
+        } else {
+            usize::from(self.chr_bank_0 & !1) | usize::from(addr >> 12 & 1)
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn observe_cpu_read(&mut self, _addr: u16) {
+        self.last_access_was_write = false;
+    }
+
+    /// Disabled or missing PRG-RAM leaves the data bus open.
+    fn is_mapped(&self, addr: u16) -> bool {
+        match addr {
+            super::PRG_ROM_START..=0xFFFF => true,
+            super::PRG_RAM_START..=0x7FFF => {
+                self.is_prg_ram_enabled() && !self.cartridge.prg_ram.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            super::PRG_ROM_START..=0xFFFF if self.board == Board::Serom => {
+                super::banked_read(&self.cartridge.prg_rom, 0x8000, 0, addr)
+            }
+            super::PRG_ROM_START..=0xFFFF => super::banked_read(
+                &self.cartridge.prg_rom,
+                PRG_ROM_BANK_SIZE,
+                self.prg_rom_bank(addr),
+                addr,
+            ),
+            super::PRG_RAM_START..=0x7FFF if self.is_prg_ram_enabled() => super::banked_read(
+                &self.cartridge.prg_ram,
+                PRG_RAM_BANK_SIZE,
+                self.prg_ram_bank(),
+                addr,
+            ),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            super::PRG_ROM_START..=0xFFFF => {
+                // Writes on consecutive cycles (the double write of
+                // read-modify-write instructions) only see the first one.
+                if !self.last_access_was_write {
+                    self.write_shift_register(addr, data);
+                }
+                self.last_access_was_write = true;
+            }
+            super::PRG_RAM_START..=0x7FFF if self.is_prg_ram_enabled() => {
+                let bank = self.prg_ram_bank();
+                super::banked_write(
+                    &mut self.cartridge.prg_ram,
+                    PRG_RAM_BANK_SIZE,
+                    bank,
+                    addr,
+                    data,
+                )
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_bank(addr);
+        super::read_chr(&self.cartridge, CHR_BANK_SIZE, bank, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_bank(addr);
+        super::write_chr(&mut self.cartridge, CHR_BANK_SIZE, bank, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::create_test_cartridge;
+
+    fn write_register(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for i in 0..SHIFT_REGISTER_WRITES {
+            mmc1.cpu_write(addr, value >> i);
+            mmc1.observe_cpu_read(0x0000);
+        }
+    }
+
+    #[test]
+    fn test_mmc1_power_on_fixes_last_bank() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 8, 2));
+        assert_eq!(0, mmc1.cpu_read(0x8000));
+        assert_eq!(7, mmc1.cpu_read(0xC000));
+        write_register(&mut mmc1, 0xE000, 3);
+        assert_eq!(3, mmc1.cpu_read(0x8000));
+        assert_eq!(7, mmc1.cpu_read(0xFFFF));
+    }
+
+    #[test]
+    fn test_mmc1_prg_modes() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 8, 2));
+        write_register(&mut mmc1, 0xE000, 5);
+        write_register(&mut mmc1, 0x8000, 0b0_0000);
+        assert_eq!(4, mmc1.cpu_read(0x8000));
+        assert_eq!(5, mmc1.cpu_read(0xC000));
+        write_register(&mut mmc1, 0x8000, 0b0_1000);
+        assert_eq!(0, mmc1.cpu_read(0x8000));
+        assert_eq!(5, mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc1_reset_bit() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 8, 2));
+        write_register(&mut mmc1, 0x8000, 0b0_0000);
+        mmc1.cpu_write(0xE000, 1);
+        mmc1.observe_cpu_read(0x0000);
+        mmc1.cpu_write(0x8000, SHIFT_REGISTER_RESET);
+        mmc1.observe_cpu_read(0x0000);
+        write_register(&mut mmc1, 0xE000, 2);
+        assert_eq!(2, mmc1.cpu_read(0x8000));
+        assert_eq!(7, mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc1_consecutive_writes_are_ignored() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 8, 2));
+        for bit in [1, 0, 1, 0, 0] {
+            mmc1.cpu_write(0xE000, 0);
+            mmc1.cpu_write(0xE000, bit);
+            mmc1.observe_cpu_read(0x0000);
+        }
+        assert_eq!(0, mmc1.cpu_read(0x8000));
+    }
+
+    #[test]
+    fn test_mmc1_chr_modes() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 2, 4));
+        write_register(&mut mmc1, 0xA000, 3);
+        assert_eq!(1, mmc1.ppu_read(0x0000));
+        assert_eq!(1, mmc1.ppu_read(0x1000));
+        write_register(&mut mmc1, 0x8000, 0b1_1100);
+        write_register(&mut mmc1, 0xC000, 6);
+        assert_eq!(1, mmc1.ppu_read(0x0000));
+        assert_eq!(3, mmc1.ppu_read(0x1000));
+    }
+
+    #[test]
+    fn test_mmc1_mirroring() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 2, 2));
+        write_register(&mut mmc1, 0x8000, 0b0_0010);
+        assert_eq!(Mirroring::Vertical, mmc1.mirroring());
+        write_register(&mut mmc1, 0x8000, 0b0_0001);
+        assert_eq!(Mirroring::SingleScreenUpper, mmc1.mirroring());
+    }
+
+    #[test]
+    fn test_mmc1_prg_ram_enable() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 2, 2));
+        mmc1.cpu_write(0x6000, 42);
+        assert_eq!(42, mmc1.cpu_read(0x6000));
+        assert!(mmc1.is_mapped(0x6000));
+        write_register(&mut mmc1, 0xE000, 0b1_0000);
+        assert!(!mmc1.is_mapped(0x6000));
+        write_register(&mut mmc1, 0xE000, 0b0_0000);
+        assert_eq!(42, mmc1.cpu_read(0x6000));
+    }
+
+    #[test]
+    fn test_snrom_detection() {
+        let mut cartridge = create_test_cartridge(1, 8, 0);
+        assert_eq!(Board::Generic, Board::from_header(&cartridge.header));
+        cartridge.header.has_battery = true;
+        assert_eq!(Board::Snrom, Board::from_header(&cartridge.header));
+        // SGROM
+        cartridge.header.format = Format::Nes2;
+        cartridge.header.prg_ram_size = 0;
+        cartridge.header.prg_nvram_size = 0;
+        assert_eq!(Board::Generic, Board::from_header(&cartridge.header));
+        cartridge.header.prg_ram_size = PRG_RAM_BANK_SIZE;
+        assert_eq!(Board::Snrom, Board::from_header(&cartridge.header));
+    }
+
+    #[test]
+    fn test_snrom_chr_line_disables_prg_ram() {
+        let mut cartridge = create_test_cartridge(1, 8, 0);
+        cartridge.header.has_battery = true;
+        let mut mmc1 = Mmc1::new(cartridge);
+        assert_eq!(Board::Snrom, mmc1.board);
+        mmc1.cpu_write(0x6000, 42);
+        write_register(&mut mmc1, 0xA000, 0b1_0000);
+        assert!(!mmc1.is_mapped(0x6000));
+        write_register(&mut mmc1, 0xA000, 0b0_0000);
+        assert_eq!(42, mmc1.cpu_read(0x6000));
+    }
+
+    #[test]
+    fn test_surom_outer_prg_bank() {
+        let mut mmc1 = Mmc1::new(create_test_cartridge(1, 32, 0));
+        assert_eq!(Board::Surom, mmc1.board);
+        assert_eq!(15, mmc1.cpu_read(0xC000));
+        write_register(&mut mmc1, 0xA000, 0b1_0000);
+        assert_eq!(16, mmc1.cpu_read(0x8000));
+        assert_eq!(31, mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_sxrom_prg_ram_banks() {
+        let mut cartridge = create_test_cartridge(1, 32, 0);
+        cartridge.header.prg_nvram_size = 4 * PRG_RAM_BANK_SIZE;
+        cartridge.header.prg_ram_size = 0;
+        cartridge.prg_ram = vec![0; 4 * PRG_RAM_BANK_SIZE];
+        let mut mmc1 = Mmc1::new(cartridge);
+        assert_eq!(Board::Sxrom, mmc1.board);
+        for bank in 0..4 {
+            write_register(&mut mmc1, 0xA000, bank << 2);
+            mmc1.cpu_write(0x6000, bank);
+        }
+        for bank in 0..4 {
+            write_register(&mut mmc1, 0xA000, bank << 2);
+            assert_eq!(bank, mmc1.cpu_read(0x6000));
+        }
+    }
+}
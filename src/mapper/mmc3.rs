@@ -0,0 +1,313 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PRG_RAM_SIZE: usize = 0x2000;
+const MMC3A_SUBMAPPER: u8 = 4;
+/// A12 has to stay low for about three CPU cycles before a rising edge
+/// clocks the counter, which filters out the sprite fetches of a scanline.
+const A12_LOW_FILTER: u64 = 10;
+
+/// The IRQ of the old MMC3A (and MMC6) only fires when the counter gets to
+/// zero from a decrement or a reload request.
+//https://www.nesdev.org/wiki/MMC3#IRQ_Specifics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revision {
+    A,
+    B,
+}
+
+//https://www.nesdev.org/wiki/MMC3
+#[derive(Clone)]
+pub struct Mmc3 {
+    cartridge: Cartridge,
+    revision: Revision,
+    bank_select: u8,
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    prg_ram_write_protected: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12: bool,
+    a12_low_since: u64,
+}
+
+impl Mmc3 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let revision = if cartridge.header.submapper == MMC3A_SUBMAPPER {
+            Revision::A
+        } else {
+            Revision::B
+        };
+        Self {
+            revision,
+            mirroring: cartridge.header.mirroring,
+            cartridge,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            // some games never enable PRG-RAM before using it
+            prg_ram_enabled: true,
+            prg_ram_write_protected: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            a12_low_since: 0,
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        // banks past the end of a PRG smaller than 16 KiB wrap around
+        let second_last_bank = (self.cartridge.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(2);
+        let is_swapped = self.bank_select & 0b0100_0000 != 0;
+        match (addr, is_swapped) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => usize::from(self.registers[6]),
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last_bank,
+            (0xA000..=0xBFFF, _) => usize::from(self.registers[7]),
+            _ => second_last_bank + 1,
+        }
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        let addr = if self.bank_select & 0b1000_0000 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let slot = usize::from(addr >> 10 & 0b111);
+        match slot {
+            0..=3 => usize::from(self.registers[slot / 2] & !1) | slot & 1,
+            _ => usize::from(self.registers[slot - 2]),
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let was_reloaded = self.irq_reload;
+        let previous_counter = self.irq_counter;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        let triggers = match self.revision {
+            Revision::A => self.irq_counter == 0 && (previous_counter != 0 || was_reloaded),
+            Revision::B => self.irq_counter == 0,
+        };
+        if triggers && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            super::PRG_ROM_START..=0xFFFF => super::banked_read(
+                &self.cartridge.prg_rom,
+                PRG_BANK_SIZE,
+                self.prg_bank(addr),
+                addr,
+            ),
+            super::PRG_RAM_START..=0x7FFF if self.prg_ram_enabled => {
+                super::banked_read(&self.cartridge.prg_ram, PRG_RAM_SIZE, 0, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let is_even = addr & 1 == 0;
+        match addr {
+            super::PRG_RAM_START..=0x7FFF
+                if self.prg_ram_enabled && !self.prg_ram_write_protected =>
+            {
+                super::banked_write(&mut self.cartridge.prg_ram, PRG_RAM_SIZE, 0, addr, data)
+            }
+            0x8000..=0x9FFF if is_even => self.bank_select = data,
+            0x8000..=0x9FFF => self.registers[usize::from(self.bank_select & 0b111)] = data,
+            0xA000..=0xBFFF if is_even => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0xA000..=0xBFFF => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.prg_ram_write_protected = data & 0b0100_0000 != 0;
+            }
+            0xC000..=0xDFFF if is_even => self.irq_latch = data,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if is_even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        super::read_chr(&self.cartridge, CHR_BANK_SIZE, self.chr_bank(addr), addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_bank(addr);
+        super::write_chr(&mut self.cartridge, CHR_BANK_SIZE, bank, addr, data)
+    }
+
+    fn observe_ppu_addr(&mut self, addr: u16, ppu_cycle: u64) {
+        let a12 = addr & 0x1000 != 0;
+        if a12 && !self.a12 && ppu_cycle.saturating_sub(self.a12_low_since) >= A12_LOW_FILTER {
+            self.clock_irq_counter();
+        }
+        if !a12 && self.a12 {
+            self.a12_low_since = ppu_cycle;
+        }
+        self.a12 = a12;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.cartridge.header.mirroring == Mirroring::FourScreen {
+            Mirroring::FourScreen
+        } else {
+            self.mirroring
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::create_test_cartridge;
+
+    fn create_mmc3(revision: Revision) -> Mmc3 {
+        let mut mmc3 = Mmc3::new(create_test_cartridge(4, 8, 16));
+        mmc3.revision = revision;
+        mmc3
+    }
+
+    /// Background at $0000 and sprites at $1000 : one rising edge per scanline
+    fn render_scanline(mmc3: &mut Mmc3, scanline: u64) {
+        let start = scanline * 341;
+        mmc3.observe_ppu_addr(0x0000, start);
+        mmc3.observe_ppu_addr(0x2000, start + 257);
+        for sprite in 0..8 {
+            let dot = start + 260 + sprite * 8;
+            mmc3.observe_ppu_addr(0x1000, dot);
+            mmc3.observe_ppu_addr(0x2000, dot + 5);
+        }
+    }
+
+    #[test]
+    fn test_mmc3_prg_banks() {
+        let mut mmc3 = create_mmc3(Revision::B);
+        mmc3.cpu_write(0x8000, 6);
+        mmc3.cpu_write(0x8001, 3);
+        mmc3.cpu_write(0x8000, 7);
+        mmc3.cpu_write(0x8001, 5);
+        // 16KB test banks hold two 8KB banks each
+        assert_eq!(1, mmc3.cpu_read(0x8000));
+        assert_eq!(2, mmc3.cpu_read(0xA000));
+        assert_eq!(7, mmc3.cpu_read(0xC000));
+        assert_eq!(7, mmc3.cpu_read(0xE000));
+        mmc3.cpu_write(0x8000, 0b0100_0000);
+        assert_eq!(7, mmc3.cpu_read(0x8000));
+        assert_eq!(1, mmc3.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_mmc3_prg_smaller_than_two_banks() {
+        let mut cartridge = create_test_cartridge(4, 1, 1);
+        cartridge.prg_rom.truncate(0x400);
+        cartridge.prg_rom[0x3FF] = 42;
+        let mut mmc3 = Mmc3::new(cartridge);
+        for addr in [0x9FFF, 0xBFFF, 0xDFFF, 0xFFFF] {
+            assert_eq!(42, mmc3.cpu_read(addr));
+        }
+    }
+
+    #[test]
+    fn test_mmc3_chr_banks() {
+        let mut mmc3 = create_mmc3(Revision::B);
+        for (register, bank) in [(0, 8), (2, 19)] {
+            mmc3.cpu_write(0x8000, register);
+            mmc3.cpu_write(0x8001, bank);
+        }
+        // 8KB test banks hold eight 1KB banks each
+        assert_eq!(1, mmc3.ppu_read(0x0000));
+        assert_eq!(1, mmc3.ppu_read(0x0400));
+        assert_eq!(2, mmc3.ppu_read(0x1000));
+        mmc3.cpu_write(0x8000, 0b1000_0000);
+        assert_eq!(1, mmc3.ppu_read(0x1000));
+        assert_eq!(2, mmc3.ppu_read(0x0000));
+    }
+
+    #[test]
+    fn test_mmc3_mirroring_and_prg_ram_protect() {
+        let mut mmc3 = create_mmc3(Revision::B);
+        mmc3.cpu_write(0xA000, 1);
+        assert_eq!(Mirroring::Horizontal, mmc3.mirroring());
+        mmc3.cpu_write(0x6000, 42);
+        mmc3.cpu_write(0xA001, 0b1100_0000);
+        mmc3.cpu_write(0x6000, 24);
+        assert_eq!(42, mmc3.cpu_read(0x6000));
+        mmc3.cpu_write(0xA001, 0);
+        assert_eq!(0, mmc3.cpu_read(0x6000));
+    }
+
+    #[test]
+    fn test_mmc3_scanline_irq() {
+        let mut mmc3 = create_mmc3(Revision::B);
+        mmc3.cpu_write(0xC000, 3);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+        for scanline in 0..3 {
+            render_scanline(&mut mmc3, scanline);
+            assert!(!mmc3.irq(), "scanline {scanline}");
+        }
+        render_scanline(&mut mmc3, 3);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xE000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn test_mmc3_disabled_irq() {
+        let mut mmc3 = create_mmc3(Revision::B);
+        mmc3.cpu_write(0xC000, 0);
+        mmc3.cpu_write(0xC001, 0);
+        render_scanline(&mut mmc3, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn test_mmc3_zero_latch_revisions() {
+        for (revision, expected) in [(Revision::A, false), (Revision::B, true)] {
+            let mut mmc3 = create_mmc3(revision);
+            mmc3.cpu_write(0xC000, 0);
+            mmc3.cpu_write(0xC001, 0);
+            mmc3.cpu_write(0xE001, 0);
+            render_scanline(&mut mmc3, 0);
+            mmc3.cpu_write(0xE000, 0);
+            mmc3.cpu_write(0xE001, 0);
+            render_scanline(&mut mmc3, 1);
+            assert_eq!(expected, mmc3.irq(), "{revision:?}");
+        }
+    }
+}
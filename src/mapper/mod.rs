@@ -0,0 +1,174 @@
+mod axrom;
+mod cnrom;
+mod mmc1;
+mod mmc3;
+mod nrom;
+mod uxrom;
+
+use crate::cartridge::{Cartridge, Error, Mirroring};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const PRG_RAM_START: u16 = 0x6000;
+pub const PRG_ROM_START: u16 = 0x8000;
+const PRG_RAM_WINDOW_MASK: u16 = 0x1FFF;
+
+/// Cartridge hardware seen from the CPU ($4020-$FFFF) and from the PPU
+/// pattern tables ($0000-$1FFF).
+pub trait Mapper: MapperClone {
+    /// Called for every CPU read, even outside of cartridge space, for the
+    /// mappers that need to know what happens on the whole bus.
+    fn observe_cpu_read(&mut self, _addr: u16) {}
+
+    /// Whether the cartridge answers CPU reads at `addr`, which are open bus
+    /// otherwise. Most boards don't decode the expansion space.
+    fn is_mapped(&self, addr: u16) -> bool {
+        addr >= PRG_RAM_START
+    }
+
+    /// The value `cpu_read` would return, without its side effects, for
+    /// debuggers and trace loggers.
+    fn peek(&self, addr: u16) -> u8;
+
+    /// Reads of the boards supported so far have no side effects.
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    fn ppu_read(&mut self, addr: u16) -> u8;
+
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    /// Called by the PPU for every address it puts on its bus, `ppu_cycle`
+    /// counts the dots since power on.
+    fn observe_ppu_addr(&mut self, _addr: u16, _ppu_cycle: u64) {}
+
+    fn mirroring(&self) -> Mirroring;
+
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+/// Lets a console be cloned with its cartridge, for save states.
+pub trait MapperClone {
+    fn clone_box(&self) -> Box<dyn Mapper>;
+}
+
+impl<T: Mapper + Clone + 'static> MapperClone for T {
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+}
+
+/// The cartridge is wired to both the CPU and the PPU buses.
+pub type SharedMapper = Rc<RefCell<Box<dyn Mapper>>>;
+
+pub fn create(cartridge: Cartridge) -> Result<Box<dyn Mapper>, Error> {
+    match cartridge.header.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(cartridge))),
+        1 => Ok(Box::new(mmc1::Mmc1::new(cartridge))),
+        2 => Ok(Box::new(uxrom::Uxrom::new(cartridge))),
+        3 => Ok(Box::new(cnrom::Cnrom::new(cartridge))),
+        4 => Ok(Box::new(mmc3::Mmc3::new(cartridge))),
+        7 => Ok(Box::new(axrom::Axrom::new(cartridge))),
+        mapper => Err(Error::UnsupportedMapper(mapper)),
+    }
+}
+
+/// Reads `addr` inside `bank`, banks past the end of `memory` wrap around
+/// like they do on boards with unconnected upper bank lines.
+fn banked_read(memory: &[u8], bank_size: usize, bank: usize, addr: u16) -> u8 {
+    if memory.is_empty() {
+        return 0;
+    }
+    memory[(bank * bank_size + (usize::from(addr) & (bank_size - 1))) % memory.len()]
+}
+
+fn banked_write(memory: &mut [u8], bank_size: usize, bank: usize, addr: u16, data: u8) {
+    if memory.is_empty() {
+        return;
+    }
+    let len = memory.len();
+    memory[(bank * bank_size + (usize::from(addr) & (bank_size - 1))) % len] = data;
+}
+
+fn read_prg_ram(cartridge: &Cartridge, addr: u16) -> u8 {
+    banked_read(&cartridge.prg_ram, 0x2000, 0, addr & PRG_RAM_WINDOW_MASK)
+}
+
+fn write_prg_ram(cartridge: &mut Cartridge, addr: u16, data: u8) {
+    banked_write(
+        &mut cartridge.prg_ram,
+        0x2000,
+        0,
+        addr & PRG_RAM_WINDOW_MASK,
+        data,
+    )
+}
+
+fn read_chr(cartridge: &Cartridge, bank_size: usize, bank: usize, addr: u16) -> u8 {
+    banked_read(cartridge.chr(), bank_size, bank, addr)
+}
+
+fn write_chr(cartridge: &mut Cartridge, bank_size: usize, bank: usize, addr: u16, data: u8) {
+    if cartridge.has_chr_ram() {
+        banked_write(&mut cartridge.chr_ram, bank_size, bank, addr, data)
+    }
+}
+
+#[cfg(test)]
+fn create_test_cartridge(mapper: u8, prg_banks: u8, chr_banks: u8) -> Cartridge {
+    use crate::cartridge::{CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE};
+
+    let mut rom = vec![
+        b'N',
+        b'E',
+        b'S',
+        0x1A,
+        prg_banks,
+        chr_banks,
+        mapper << 4,
+        mapper & 0xF0,
+    ];
+    rom.resize(16, 0);
+    // every byte of a bank holds the bank number
+    for bank in 0..prg_banks {
+        rom.extend(vec![bank; PRG_ROM_BANK_SIZE]);
+    }
+    for bank in 0..chr_banks {
+        rom.extend(vec![bank; CHR_ROM_BANK_SIZE]);
+    }
+    Cartridge::from_bytes(&rom).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_supported_mappers() {
+        for mapper in [0, 1, 2, 3, 4, 7] {
+            assert!(create(create_test_cartridge(mapper, 2, 1)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_create_unsupported_mapper() {
+        assert_eq!(
+            Some(Error::UnsupportedMapper(255)),
+            create(create_test_cartridge(255, 2, 1)).err()
+        );
+    }
+
+    #[test]
+    fn test_banked_read_wraps_around() {
+        let memory = [0, 1, 2, 3];
+        assert_eq!(1, banked_read(&memory, 2, 0, 0x4001));
+        assert_eq!(2, banked_read(&memory, 2, 1, 0x0000));
+        assert_eq!(0, banked_read(&memory, 2, 2, 0x0000));
+        assert_eq!(0, banked_read(&[], 2, 1, 0x0000));
+    }
+}
@@ -0,0 +1,88 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring, CHR_ROM_BANK_SIZE};
+
+//https://www.nesdev.org/wiki/NROM
+#[derive(Clone)]
+pub struct Nrom {
+    cartridge: Cartridge,
+}
+
+impl Nrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        Self { cartridge }
+    }
+}
+
+impl Mapper for Nrom {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            super::PRG_ROM_START..=0xFFFF => {
+                // 16KB carts are mirrored in $C000-$FFFF
+                super::banked_read(&self.cartridge.prg_rom, 0x8000, 0, addr)
+            }
+            super::PRG_RAM_START..=0x7FFF => super::read_prg_ram(&self.cartridge, addr),
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let super::PRG_RAM_START..=0x7FFF = addr {
+            super::write_prg_ram(&mut self.cartridge, addr, data)
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        super::read_chr(&self.cartridge, CHR_ROM_BANK_SIZE, 0, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        super::write_chr(&mut self.cartridge, CHR_ROM_BANK_SIZE, 0, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.cartridge.header.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::create_test_cartridge;
+
+    #[test]
+    fn test_nrom_128_is_mirrored() {
+        let mut nrom = Nrom::new(create_test_cartridge(0, 1, 1));
+        assert_eq!(0, nrom.cpu_read(0x8000));
+        assert_eq!(0, nrom.cpu_read(0xFFFF));
+    }
+
+    #[test]
+    fn test_nrom_256() {
+        let mut nrom = Nrom::new(create_test_cartridge(0, 2, 1));
+        assert_eq!(0, nrom.cpu_read(0xBFFF));
+        assert_eq!(1, nrom.cpu_read(0xC000));
+        nrom.cpu_write(0x8000, 42);
+        assert_eq!(0, nrom.cpu_read(0x8000));
+    }
+
+    #[test]
+    fn test_nrom_prg_ram() {
+        let mut nrom = Nrom::new(create_test_cartridge(0, 1, 1));
+        nrom.cpu_write(0x6123, 42);
+        assert_eq!(42, nrom.cpu_read(0x6123));
+    }
+
+    #[test]
+    fn test_nrom_chr_rom_is_read_only() {
+        let mut nrom = Nrom::new(create_test_cartridge(0, 1, 1));
+        nrom.ppu_write(0x0010, 42);
+        assert_eq!(0, nrom.ppu_read(0x0010));
+    }
+
+    #[test]
+    fn test_nrom_chr_ram() {
+        let mut nrom = Nrom::new(create_test_cartridge(0, 1, 0));
+        nrom.ppu_write(0x1010, 42);
+        assert_eq!(42, nrom.ppu_read(0x1010));
+    }
+}
@@ -0,0 +1,84 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring, CHR_ROM_BANK_SIZE, PRG_ROM_BANK_SIZE};
+
+//https://www.nesdev.org/wiki/UxROM
+#[derive(Clone)]
+pub struct Uxrom {
+    cartridge: Cartridge,
+    prg_bank: usize,
+    last_prg_bank: usize,
+}
+
+impl Uxrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        // NES 2.0 allows less than a bank, which then repeats
+        let last_prg_bank = (cartridge.prg_rom.len() / PRG_ROM_BANK_SIZE).saturating_sub(1);
+        Self {
+            cartridge,
+            prg_bank: 0,
+            last_prg_bank,
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn peek(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0xC000..=0xFFFF => self.last_prg_bank,
+            super::PRG_ROM_START..=0xBFFF => self.prg_bank,
+            _ => return 0,
+        };
+        super::banked_read(&self.cartridge.prg_rom, PRG_ROM_BANK_SIZE, bank, addr)
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= super::PRG_ROM_START {
+            self.prg_bank = usize::from(data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        super::read_chr(&self.cartridge, CHR_ROM_BANK_SIZE, 0, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        super::write_chr(&mut self.cartridge, CHR_ROM_BANK_SIZE, 0, addr, data)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.cartridge.header.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::create_test_cartridge;
+
+    #[test]
+    fn test_uxrom_bank_switching() {
+        let mut uxrom = Uxrom::new(create_test_cartridge(2, 8, 0));
+        assert_eq!(0, uxrom.cpu_read(0x8000));
+        assert_eq!(7, uxrom.cpu_read(0xC000));
+        uxrom.cpu_write(0x8000, 5);
+        assert_eq!(5, uxrom.cpu_read(0xBFFF));
+        assert_eq!(7, uxrom.cpu_read(0xFFFF));
+    }
+
+    #[test]
+    fn test_uxrom_chr_ram() {
+        let mut uxrom = Uxrom::new(create_test_cartridge(2, 2, 0));
+        uxrom.ppu_write(0x0ABC, 42);
+        assert_eq!(42, uxrom.ppu_read(0x0ABC));
+    }
+
+    #[test]
+    fn test_uxrom_prg_smaller_than_a_bank() {
+        let mut cartridge = create_test_cartridge(2, 1, 0);
+        cartridge.prg_rom.truncate(0x400);
+        cartridge.prg_rom[0x3FF] = 42;
+        let mut uxrom = Uxrom::new(cartridge);
+        assert_eq!(42, uxrom.cpu_read(0xBFFF));
+        assert_eq!(42, uxrom.cpu_read(0xFFFF));
+    }
+}
@@ -0,0 +1,187 @@
+//! Layout of the CPU address space, from which the bus is built.
+//https://www.nesdev.org/wiki/CPU_memory_map
+use std::fmt;
+use std::ops::RangeInclusive;
+
+const RAM_SIZE: u16 = 0x0800;
+const PPU_REGISTERS_SIZE: u16 = 8;
+
+/// What answers in a range of addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Internal RAM
+    Ram,
+    PpuRegisters,
+    /// APU registers and joypads
+    ApuIo,
+    /// Registers of the CPU test mode, disabled on retail consoles
+    TestMode,
+    /// Cartridge space before the SRAM, used by a few mappers
+    Expansion,
+    /// Battery-backed or work RAM on the cartridge
+    Sram,
+    PrgRom,
+}
+
+impl Region {
+    /// Answered by the cartridge rather than by the console
+    pub fn is_cartridge(&self) -> bool {
+        matches!(self, Region::Expansion | Region::Sram | Region::PrgRom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub range: RangeInclusive<u16>,
+    pub region: Region,
+    /// Size of the block repeated over the range, the region only decodes
+    /// the low address lines
+    pub mirror_size: Option<u16>,
+}
+
+impl Mapping {
+    pub fn new(range: RangeInclusive<u16>, region: Region) -> Self {
+        Self {
+            range,
+            region,
+            mirror_size: None,
+        }
+    }
+
+    pub fn mirrored(mut self, size: u16) -> Self {
+        self.mirror_size = Some(size);
+        self
+    }
+
+    /// Bytes actually decoded by the region
+    pub fn size(&self) -> usize {
+        self.mirror_size.map_or(self.range.len(), usize::from)
+    }
+
+    /// Address of the first mirror of `addr`.
+    fn mirror(&self, addr: u16) -> u16 {
+        let start = *self.range.start();
+        match self.mirror_size {
+            Some(size) => start + (addr - start) % size,
+            None => addr,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Overlap(u16),
+    EmptyMirror(Region),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Overlap(addr) => write!(f, "${addr:04X} is mapped twice"),
+            Error::EmptyMirror(region) => write!(f, "{region:?} is mirrored every 0 bytes"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Mappings sorted by address, the addresses between them are open bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    mappings: Vec<Mapping>,
+}
+
+impl MemoryMap {
+    pub fn new(mut mappings: Vec<Mapping>) -> Result<Self, Error> {
+        mappings.sort_by_key(|mapping| *mapping.range.start());
+        for mapping in &mappings {
+            if mapping.mirror_size == Some(0) {
+                return Err(Error::EmptyMirror(mapping.region));
+            }
+        }
+        for pair in mappings.windows(2) {
+            if pair[1].range.start() <= pair[0].range.end() {
+                return Err(Error::Overlap(*pair[1].range.start()));
+            }
+        }
+        Ok(Self { mappings })
+    }
+
+    /// The map of the NES, which decodes the whole address space.
+    pub fn nes() -> Self {
+        Self::new(vec![
+            Mapping::new(0x0000..=0x1FFF, Region::Ram).mirrored(RAM_SIZE),
+            Mapping::new(0x2000..=0x3FFF, Region::PpuRegisters).mirrored(PPU_REGISTERS_SIZE),
+            Mapping::new(0x4000..=0x4017, Region::ApuIo),
+            Mapping::new(0x4018..=0x401F, Region::TestMode),
+            Mapping::new(0x4020..=0x5FFF, Region::Expansion),
+            Mapping::new(0x6000..=0x7FFF, Region::Sram),
+            Mapping::new(0x8000..=0xFFFF, Region::PrgRom),
+        ])
+        .expect("the NES map has no overlap")
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// The mapping answering at `addr` and the address with its mirroring
+    /// undone, `None` for open bus.
+    pub fn decode(&self, addr: u16) -> Option<(&Mapping, u16)> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.range.contains(&addr))
+            .map(|mapping| (mapping, mapping.mirror(addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nes_map_covers_every_address() {
+        let map = MemoryMap::nes();
+        for addr in 0..=u16::MAX {
+            let (mapping, mirrored) = map.decode(addr).unwrap();
+            let expected = match addr {
+                0x0000..=0x1FFF => (Region::Ram, addr & 0x07FF),
+                0x2000..=0x3FFF => (Region::PpuRegisters, addr & 0x2007),
+                0x4000..=0x4017 => (Region::ApuIo, addr),
+                0x4018..=0x401F => (Region::TestMode, addr),
+                0x4020..=0x5FFF => (Region::Expansion, addr),
+                0x6000..=0x7FFF => (Region::Sram, addr),
+                0x8000..=0xFFFF => (Region::PrgRom, addr),
+            };
+            assert_eq!(expected, (mapping.region, mirrored), "${addr:04X}");
+        }
+    }
+
+    #[test]
+    fn test_gaps_are_open_bus() {
+        let map = MemoryMap::new(vec![
+            Mapping::new(0x8000..=0xFFFF, Region::PrgRom),
+            Mapping::new(0x0000..=0x07FF, Region::Ram),
+        ])
+        .unwrap();
+        assert_eq!(Region::Ram, map.mappings()[0].region);
+        assert!(map.decode(0x0800).is_none());
+        assert!(map.decode(0x7FFF).is_none());
+        assert_eq!(0xFFFF, map.decode(0xFFFF).unwrap().1);
+    }
+
+    #[test]
+    fn test_invalid_maps() {
+        assert_eq!(
+            Err(Error::Overlap(0x1000)),
+            MemoryMap::new(vec![
+                Mapping::new(0x0000..=0x1FFF, Region::Ram),
+                Mapping::new(0x1000..=0x2FFF, Region::PpuRegisters),
+            ])
+        );
+        assert_eq!(
+            Err(Error::EmptyMirror(Region::Ram)),
+            MemoryMap::new(vec![Mapping::new(0x0000..=0x1FFF, Region::Ram).mirrored(0)])
+        );
+    }
+}
@@ -0,0 +1,332 @@
+pub mod palette;
+mod register;
+mod render;
+
+pub use render::{FRAME_HEIGHT, FRAME_WIDTH};
+
+use crate::cartridge::{Mirroring, Timing};
+use crate::mapper::SharedMapper;
+use crate::traits::Device;
+
+pub const PPU_REGISTERS_START: u16 = 0x2000;
+const PPU_REGISTERS_COUNT: u16 = 8;
+const DOTS_PER_SCANLINE: u16 = 341;
+const NAMETABLES_START: u16 = 0x2000;
+const NAMETABLE_SIZE: u16 = 0x0400;
+const PALETTE_START: u16 = 0x3F00;
+const VRAM_SIZE: usize = 0x1000;
+const PALETTE_SIZE: usize = 32;
+const OAM_SIZE: usize = 256;
+
+#[derive(Clone)]
+pub struct Ppu {
+    mapper: Option<SharedMapper>,
+    ctrl: register::Control,
+    mask: register::Mask,
+    status: register::Status,
+    oam_addr: u8,
+    oam: [u8; OAM_SIZE],
+    vram: [u8; VRAM_SIZE],
+    palette: [u8; PALETTE_SIZE],
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+    read_buffer: u8,
+    io_latch: u8,
+    vblank_scanline: u16,
+    prerender_scanline: u16,
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    cycle: u64,
+    skips_odd_frame_dot: bool,
+    background: render::Background,
+    sprites: [render::Sprite; 8],
+    sprite_count: usize,
+    next_sprites: [render::Sprite; 8],
+    next_sprite_count: usize,
+    frame_buffer: Box<[u8]>,
+}
+
+impl Ppu {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            mapper: None,
+            ctrl: register::Control::empty(),
+            mask: register::Mask::empty(),
+            status: register::Status::empty(),
+            oam_addr: 0,
+            oam: [0; OAM_SIZE],
+            vram: [0; VRAM_SIZE],
+            palette: [0; PALETTE_SIZE],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            vblank_scanline: Timing::Ntsc.vblank_scanline(),
+            prerender_scanline: Timing::Ntsc.scanlines_per_frame() - 1,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            cycle: 0,
+            skips_odd_frame_dot: true,
+            background: Default::default(),
+            sprites: Default::default(),
+            sprite_count: 0,
+            next_sprites: Default::default(),
+            next_sprite_count: 0,
+            frame_buffer: vec![0; FRAME_WIDTH * FRAME_HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    pub fn connect(&mut self, mapper: SharedMapper) {
+        self.mapper = Some(mapper);
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.vblank_scanline = timing.vblank_scanline();
+        self.prerender_scanline = timing.scanlines_per_frame() - 1;
+        // only the NTSC PPU shortens odd frames
+        self.skips_odd_frame_dot = matches!(timing, Timing::Ntsc | Timing::MultiRegion);
+    }
+
+    /// State of the /NMI output, the CPU triggers on its falling edge.
+    pub fn nmi(&self) -> bool {
+        self.status.contains(register::Status::VBLANK)
+            && self.ctrl.contains(register::Control::GENERATE_NMI)
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn is_in_vblank(&self) -> bool {
+        let position = (self.scanline, self.dot);
+        position >= (self.vblank_scanline, 1) && position < (self.prerender_scanline, 1)
+    }
+
+    pub fn tick(&mut self, dots: u32) {
+        for _ in 0..dots {
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        self.cycle += 1;
+        self.dot += 1;
+        //https://www.nesdev.org/wiki/PPU_frame_timing#Even/Odd_Frames
+        let skips_dot = self.skips_odd_frame_dot
+            && self.frame % 2 == 1
+            && self.scanline == self.prerender_scanline
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.is_rendering_enabled();
+        if self.dot == DOTS_PER_SCANLINE || skips_dot {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > self.prerender_scanline {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+
+        if self.dot == 1 && self.scanline == self.vblank_scanline {
+            self.status.insert(register::Status::VBLANK);
+        } else if self.dot == 1 && self.scanline == self.prerender_scanline {
+            self.status.remove(
+                register::Status::VBLANK
+                    | register::Status::SPRITE_ZERO_HIT
+                    | register::Status::SPRITE_OVERFLOW,
+            );
+        }
+        self.render_dot();
+    }
+
+    //https://www.nesdev.org/wiki/PPU_registers
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        let value = match addr % PPU_REGISTERS_COUNT {
+            2 => {
+                let value = self.status.bits() | self.io_latch & 0b0001_1111;
+                self.status.remove(register::Status::VBLANK);
+                self.w = false;
+                value
+            }
+            4 => self.oam[usize::from(self.oam_addr)],
+            7 => {
+                let addr = self.v & register::VRAM_ADDR_MASK;
+                let value = if addr < PALETTE_START {
+                    let value = self.read_buffer;
+                    self.read_buffer = self.read_memory(addr);
+                    value
+                } else {
+                    // the buffer gets the nametable byte "under" the palette
+                    self.read_buffer = self.read_memory(addr - 0x1000);
+                    self.read_memory(addr) | self.io_latch & 0b1100_0000
+                };
+                self.increment_vram_addr();
+                value
+            }
+            _ => self.io_latch,
+        };
+        self.io_latch = value;
+        value
+    }
+
+    /// What `read_register` returns, without clearing VBlank and the write
+    /// toggle nor moving the VRAM address.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr % PPU_REGISTERS_COUNT {
+            2 => self.status.bits() | self.io_latch & 0b0001_1111,
+            4 => self.oam[usize::from(self.oam_addr)],
+            7 => {
+                let addr = self.v & register::VRAM_ADDR_MASK;
+                if addr < PALETTE_START {
+                    self.read_buffer
+                } else {
+                    self.palette[palette_index(addr)] | self.io_latch & 0b1100_0000
+                }
+            }
+            _ => self.io_latch,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        self.io_latch = data;
+        match addr % PPU_REGISTERS_COUNT {
+            0 => {
+                self.ctrl = register::Control::from_bits_truncate(data);
+                self.t = self.t & !register::NAMETABLE_MASK | u16::from(data & 0b11) << 10;
+            }
+            1 => self.mask = register::Mask::from_bits_truncate(data),
+            2 => {}
+            3 => self.oam_addr = data,
+            4 => {
+                self.oam[usize::from(self.oam_addr)] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if self.w {
+                    self.t = self.t & !(register::FINE_Y_MASK | register::COARSE_Y_MASK)
+                        | u16::from(data & 0b111) << 12
+                        | u16::from(data >> 3) << 5;
+                } else {
+                    self.t = self.t & !register::COARSE_X_MASK | u16::from(data >> 3);
+                    self.x = data & 0b111;
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if self.w {
+                    self.t = self.t & 0xFF00 | u16::from(data);
+                    self.v = self.t;
+                } else {
+                    self.t = self.t & 0x00FF | u16::from(data & 0b0011_1111) << 8;
+                }
+                self.w = !self.w;
+            }
+            _ => {
+                self.write_memory(self.v & register::VRAM_ADDR_MASK, data);
+                self.increment_vram_addr();
+            }
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let increment = if self.ctrl.contains(register::Control::VRAM_ADDR_INCREMENT) {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(increment) & 0x7FFF;
+    }
+
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        self.observe_addr(addr);
+        match addr {
+            0x0000..=0x1FFF => self
+                .mapper
+                .as_ref()
+                .map_or(0, |mapper| mapper.borrow_mut().ppu_read(addr)),
+            NAMETABLES_START..=0x3EFF => self.vram[self.nametable_index(addr)],
+            _ => self.palette[palette_index(addr)],
+        }
+    }
+
+    fn write_memory(&mut self, addr: u16, data: u8) {
+        self.observe_addr(addr);
+        match addr {
+            0x0000..=0x1FFF => {
+                if let Some(mapper) = &self.mapper {
+                    mapper.borrow_mut().ppu_write(addr, data)
+                }
+            }
+            NAMETABLES_START..=0x3EFF => self.vram[self.nametable_index(addr)] = data,
+            _ => self.palette[palette_index(addr)] = data & 0b0011_1111,
+        }
+    }
+
+    fn observe_addr(&self, addr: u16) {
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow_mut().observe_ppu_addr(addr, self.cycle);
+        }
+    }
+
+    //https://www.nesdev.org/wiki/Mirroring#Nametable_Mirroring
+    fn nametable_index(&self, addr: u16) -> usize {
+        let offset = (addr - NAMETABLES_START) % (4 * NAMETABLE_SIZE);
+        let mirroring = self
+            .mapper
+            .as_ref()
+            .map_or(Mirroring::Horizontal, |mapper| mapper.borrow().mirroring());
+        let nametable = match (mirroring, offset / NAMETABLE_SIZE) {
+            (Mirroring::Horizontal, table) => table / 2,
+            (Mirroring::Vertical, table) => table % 2,
+            (Mirroring::SingleScreenLower, _) => 0,
+            (Mirroring::SingleScreenUpper, _) => 1,
+            (Mirroring::FourScreen, table) => table,
+        };
+        usize::from(nametable * NAMETABLE_SIZE + offset % NAMETABLE_SIZE)
+    }
+}
+
+/// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
+fn palette_index(addr: u16) -> usize {
+    let index = usize::from(addr) % PALETTE_SIZE;
+    if index & 0b1_0011 == 0b1_0000 {
+        index & !0b1_0000
+    } else {
+        index
+    }
+}
+
+impl Device for Ppu {
+    fn mapping_def(&self) -> std::ops::Range<usize> {
+        usize::from(PPU_REGISTERS_START)..usize::from(PPU_REGISTERS_START + PPU_REGISTERS_COUNT)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        self.read_register(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.write_register(addr, value)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.peek_register(addr)
+    }
+}
+
+#[cfg(test)]
+mod test;
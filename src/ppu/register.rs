@@ -0,0 +1,43 @@
+use bitflags::bitflags;
+
+bitflags! {
+    pub struct Control: u8 {
+        const GENERATE_NMI =            0b1000_0000;
+        const MASTER_SLAVE_SELECT =     0b0100_0000;
+        const SPRITE_SIZE =             0b0010_0000;
+        const BACKGROUND_PATTERN_ADDR = 0b0001_0000;
+        const SPRITE_PATTERN_ADDR =     0b0000_1000;
+        const VRAM_ADDR_INCREMENT =     0b0000_0100;
+        const NAMETABLE_HI =            0b0000_0010;
+        const NAMETABLE_LO =            0b0000_0001;
+    }
+}
+
+bitflags! {
+    pub struct Mask: u8 {
+        const EMPHASIZE_BLUE =              0b1000_0000;
+        const EMPHASIZE_GREEN =             0b0100_0000;
+        const EMPHASIZE_RED =               0b0010_0000;
+        const SHOW_SPRITES =                0b0001_0000;
+        const SHOW_BACKGROUND =             0b0000_1000;
+        const SHOW_SPRITES_LEFTMOST =       0b0000_0100;
+        const SHOW_BACKGROUND_LEFTMOST =    0b0000_0010;
+        const GREYSCALE =                   0b0000_0001;
+    }
+}
+
+bitflags! {
+    pub struct Status: u8 {
+        const VBLANK =          0b1000_0000;
+        const SPRITE_ZERO_HIT = 0b0100_0000;
+        const SPRITE_OVERFLOW = 0b0010_0000;
+    }
+}
+
+/// Layout of the internal `v` and `t` registers : 0yyy NNYY YYYX XXXX
+//https://www.nesdev.org/wiki/PPU_scrolling
+pub const COARSE_X_MASK: u16 = 0x001F;
+pub const COARSE_Y_MASK: u16 = 0x03E0;
+pub const NAMETABLE_MASK: u16 = 0x0C00;
+pub const FINE_Y_MASK: u16 = 0x7000;
+pub const VRAM_ADDR_MASK: u16 = 0x3FFF;
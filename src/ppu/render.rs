@@ -0,0 +1,391 @@
+use super::{palette_index, register, Ppu, NAMETABLES_START, PALETTE_START};
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+const VISIBLE_SCANLINES: u16 = FRAME_HEIGHT as u16;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03C0;
+const SPRITES_PER_SCANLINE: usize = 8;
+const SPRITE_SIZE: usize = 4;
+const SPRITE_COUNT: usize = 64;
+const SPRITE_FETCHES_START: u16 = 257;
+const SPRITE_FETCHES_END: u16 = 320;
+
+bitflags::bitflags! {
+    pub struct SpriteAttributes: u8 {
+        const FLIP_VERTICALLY =     0b1000_0000;
+        const FLIP_HORIZONTALLY =   0b0100_0000;
+        const BEHIND_BACKGROUND =   0b0010_0000;
+        const PALETTE =             0b0000_0011;
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Background {
+    nametable_byte: u8,
+    attribute_bits: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    pattern_shift_lo: u16,
+    pattern_shift_hi: u16,
+    attribute_shift_lo: u16,
+    attribute_shift_hi: u16,
+}
+
+#[derive(Clone, Copy)]
+pub struct Sprite {
+    y: u8,
+    tile: u8,
+    attributes: SpriteAttributes,
+    x: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    is_sprite_zero: bool,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            y: 0xFF,
+            tile: 0xFF,
+            attributes: SpriteAttributes::all(),
+            x: 0xFF,
+            pattern_lo: 0,
+            pattern_hi: 0,
+            is_sprite_zero: false,
+        }
+    }
+}
+
+/// Lines drawn by the PPU, a dot is a PPU clock cycle.
+//https://www.nesdev.org/wiki/PPU_rendering
+impl Ppu {
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+
+    pub(super) fn is_rendering_enabled(&self) -> bool {
+        self.mask
+            .intersects(register::Mask::SHOW_BACKGROUND | register::Mask::SHOW_SPRITES)
+    }
+
+    pub(super) fn render_dot(&mut self) {
+        let is_visible_scanline = self.scanline < VISIBLE_SCANLINES;
+        let is_prerender_scanline = self.scanline == self.prerender_scanline;
+        if !self.is_rendering_enabled() || !(is_visible_scanline || is_prerender_scanline) {
+            return;
+        }
+        let dot = self.dot;
+
+        if is_visible_scanline && dot == 0 {
+            self.sprites = self.next_sprites;
+            self.sprite_count = self.next_sprite_count;
+        }
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+        }
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            self.fetch_background((dot - 1) % 8);
+        }
+        match dot {
+            256 => self.increment_scroll_y(),
+            257 => {
+                self.load_background_shifters();
+                self.copy_horizontal_scroll();
+            }
+            280..=304 if is_prerender_scanline => self.copy_vertical_scroll(),
+            // unused nametable fetches
+            337 | 339 => {
+                self.read_memory(NAMETABLES_START | self.v & 0x0FFF);
+            }
+            _ => {}
+        }
+
+        if dot == SPRITE_FETCHES_START {
+            self.oam_addr = 0;
+            if is_visible_scanline {
+                self.evaluate_sprites();
+            } else {
+                self.next_sprite_count = 0;
+            }
+        }
+        if (SPRITE_FETCHES_START..=SPRITE_FETCHES_END).contains(&dot) {
+            self.fetch_sprite(dot - SPRITE_FETCHES_START);
+        }
+
+        if is_visible_scanline && (1..=256).contains(&dot) {
+            self.render_pixel(usize::from(dot - 1));
+        }
+    }
+
+    fn fetch_background(&mut self, step: u16) {
+        match step {
+            0 => {
+                self.load_background_shifters();
+                self.background.nametable_byte =
+                    self.read_memory(NAMETABLES_START | self.v & 0x0FFF);
+            }
+            2 => {
+                let v = self.v;
+                let addr = NAMETABLES_START
+                    | ATTRIBUTE_TABLE_OFFSET
+                    | v & register::NAMETABLE_MASK
+                    | (v >> 4) & 0b11_1000
+                    | (v >> 2) & 0b111;
+                let attribute = self.read_memory(addr);
+                let shift = (v >> 4) & 0b100 | v & 0b10;
+                self.background.attribute_bits = (attribute >> shift) & 0b11;
+            }
+            4 => {
+                let addr = self.background_pattern_addr();
+                self.background.pattern_lo = self.read_memory(addr);
+            }
+            6 => {
+                let addr = self.background_pattern_addr() + 8;
+                self.background.pattern_hi = self.read_memory(addr);
+            }
+            7 => self.increment_scroll_x(),
+            _ => {}
+        }
+    }
+
+    fn background_pattern_addr(&self) -> u16 {
+        let table = if self
+            .ctrl
+            .contains(register::Control::BACKGROUND_PATTERN_ADDR)
+        {
+            0x1000
+        } else {
+            0x0000
+        };
+        let fine_y = (self.v & register::FINE_Y_MASK) >> 12;
+        table + u16::from(self.background.nametable_byte) * 16 + fine_y
+    }
+
+    fn load_background_shifters(&mut self) {
+        let background = &mut self.background;
+        background.pattern_shift_lo =
+            background.pattern_shift_lo & 0xFF00 | u16::from(background.pattern_lo);
+        background.pattern_shift_hi =
+            background.pattern_shift_hi & 0xFF00 | u16::from(background.pattern_hi);
+        let [attribute_lo, attribute_hi] = [
+            background.attribute_bits & 0b01 != 0,
+            background.attribute_bits & 0b10 != 0,
+        ]
+        .map(|bit| if bit { 0x00FF } else { 0x0000 });
+        background.attribute_shift_lo = background.attribute_shift_lo & 0xFF00 | attribute_lo;
+        background.attribute_shift_hi = background.attribute_shift_hi & 0xFF00 | attribute_hi;
+    }
+
+    fn shift_background(&mut self) {
+        let background = &mut self.background;
+        background.pattern_shift_lo <<= 1;
+        background.pattern_shift_hi <<= 1;
+        background.attribute_shift_lo <<= 1;
+        background.attribute_shift_hi <<= 1;
+    }
+
+    fn increment_scroll_x(&mut self) {
+        if self.v & register::COARSE_X_MASK == register::COARSE_X_MASK {
+            self.v &= !register::COARSE_X_MASK;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_scroll_y(&mut self) {
+        if self.v & register::FINE_Y_MASK != register::FINE_Y_MASK {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !register::FINE_Y_MASK;
+        let coarse_y = (self.v & register::COARSE_Y_MASK) >> 5;
+        let coarse_y = match coarse_y {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            _ => coarse_y + 1,
+        };
+        self.v = self.v & !register::COARSE_Y_MASK | coarse_y << 5;
+    }
+
+    fn copy_horizontal_scroll(&mut self) {
+        let mask = register::COARSE_X_MASK | 0x0400;
+        self.v = self.v & !mask | self.t & mask;
+    }
+
+    fn copy_vertical_scroll(&mut self) {
+        let mask = register::FINE_Y_MASK | register::COARSE_Y_MASK | 0x0800;
+        self.v = self.v & !mask | self.t & mask;
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl.contains(register::Control::SPRITE_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Looks for the sprites of the next scanline, including the hardware bug
+    /// that makes the overflow flag unreliable.
+    //https://www.nesdev.org/wiki/PPU_sprite_evaluation
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height();
+        let scanline = self.scanline;
+        let is_in_range = |y: u8| scanline.wrapping_sub(u16::from(y)) < height;
+        let mut count = 0;
+        let mut n = 0;
+        while n < SPRITE_COUNT && count < SPRITES_PER_SCANLINE {
+            let entry = &self.oam[n * SPRITE_SIZE..(n + 1) * SPRITE_SIZE];
+            if is_in_range(entry[0]) {
+                self.next_sprites[count] = Sprite {
+                    y: entry[0],
+                    tile: entry[1],
+                    attributes: SpriteAttributes::from_bits_truncate(entry[2]),
+                    x: entry[3],
+                    pattern_lo: 0,
+                    pattern_hi: 0,
+                    is_sprite_zero: n == 0,
+                };
+                count += 1;
+            }
+            n += 1;
+        }
+        // once eight sprites are found, the PPU also increments the byte
+        // index and reads tiles, attributes and X as if they were Y
+        let mut m = 0;
+        while n < SPRITE_COUNT {
+            if is_in_range(self.oam[n * SPRITE_SIZE + m]) {
+                self.status.insert(register::Status::SPRITE_OVERFLOW);
+                break;
+            }
+            n += 1;
+            m = (m + 1) % SPRITE_SIZE;
+        }
+        for sprite in self.next_sprites[count..].iter_mut() {
+            *sprite = Sprite::default();
+        }
+        self.next_sprite_count = count;
+    }
+
+    fn fetch_sprite(&mut self, cycle: u16) {
+        let slot = usize::from(cycle / 8);
+        let plane = match cycle % 8 {
+            // garbage nametable fetches
+            0 | 2 => {
+                self.read_memory(NAMETABLES_START | self.v & 0x0FFF);
+                return;
+            }
+            4 => 0,
+            6 => 8,
+            _ => return,
+        };
+        let sprite = self.next_sprites[slot];
+        let pattern = self.read_memory(self.sprite_pattern_addr(&sprite) + plane);
+        let pattern = if slot >= self.next_sprite_count {
+            0
+        } else if sprite
+            .attributes
+            .contains(SpriteAttributes::FLIP_HORIZONTALLY)
+        {
+            pattern.reverse_bits()
+        } else {
+            pattern
+        };
+        let sprite = &mut self.next_sprites[slot];
+        if plane == 0 {
+            sprite.pattern_lo = pattern;
+        } else {
+            sprite.pattern_hi = pattern;
+        }
+    }
+
+    fn sprite_pattern_addr(&self, sprite: &Sprite) -> u16 {
+        let height = self.sprite_height();
+        let mut row = self.scanline.wrapping_sub(u16::from(sprite.y)) % height;
+        if sprite
+            .attributes
+            .contains(SpriteAttributes::FLIP_VERTICALLY)
+        {
+            row = height - 1 - row;
+        }
+        if height == 16 {
+            let table = u16::from(sprite.tile & 1) * 0x1000;
+            let tile = u16::from(sprite.tile & 0xFE) + row / 8;
+            table + tile * 16 + row % 8
+        } else {
+            let table = if self.ctrl.contains(register::Control::SPRITE_PATTERN_ADDR) {
+                0x1000
+            } else {
+                0x0000
+            };
+            table + u16::from(sprite.tile) * 16 + row
+        }
+    }
+
+    fn background_pixel(&self, x: usize) -> (u8, u8) {
+        let is_clipped = x < 8 && !self.mask.contains(register::Mask::SHOW_BACKGROUND_LEFTMOST);
+        if !self.mask.contains(register::Mask::SHOW_BACKGROUND) || is_clipped {
+            return (0, 0);
+        }
+        let background = &self.background;
+        let bit = 0x8000 >> self.x;
+        let pixel = u8::from(background.pattern_shift_hi & bit != 0) << 1
+            | u8::from(background.pattern_shift_lo & bit != 0);
+        let palette = u8::from(background.attribute_shift_hi & bit != 0) << 1
+            | u8::from(background.attribute_shift_lo & bit != 0);
+        (pixel, palette)
+    }
+
+    fn sprite_pixel(&self, x: usize) -> Option<(u8, &Sprite)> {
+        let is_clipped = x < 8 && !self.mask.contains(register::Mask::SHOW_SPRITES_LEFTMOST);
+        if !self.mask.contains(register::Mask::SHOW_SPRITES) || is_clipped {
+            return None;
+        }
+        self.sprites[..self.sprite_count].iter().find_map(|sprite| {
+            let column = x.wrapping_sub(usize::from(sprite.x));
+            if column >= 8 {
+                return None;
+            }
+            let bit = 0x80 >> column;
+            let pixel = u8::from(sprite.pattern_hi & bit != 0) << 1
+                | u8::from(sprite.pattern_lo & bit != 0);
+            (pixel != 0).then_some((pixel, sprite))
+        })
+    }
+
+    fn render_pixel(&mut self, x: usize) {
+        let (background_pixel, background_palette) = self.background_pixel(x);
+        let (palette_addr, sprite_zero_hit) = match self.sprite_pixel(x) {
+            None if background_pixel == 0 => (0, false),
+            None => (background_palette << 2 | background_pixel, false),
+            Some((pixel, sprite)) => {
+                let sprite_palette =
+                    0b1_0000 | (sprite.attributes & SpriteAttributes::PALETTE).bits() << 2 | pixel;
+                let sprite_zero_hit = sprite.is_sprite_zero && background_pixel != 0 && x != 255;
+                let is_background_visible = background_pixel != 0
+                    && sprite
+                        .attributes
+                        .contains(SpriteAttributes::BEHIND_BACKGROUND);
+                if is_background_visible {
+                    (background_palette << 2 | background_pixel, sprite_zero_hit)
+                } else {
+                    (sprite_palette, sprite_zero_hit)
+                }
+            }
+        };
+        if sprite_zero_hit {
+            self.status.insert(register::Status::SPRITE_ZERO_HIT);
+        }
+        let color_mask = if self.mask.contains(register::Mask::GREYSCALE) {
+            0x30
+        } else {
+            0x3F
+        };
+        let color = self.palette[palette_index(PALETTE_START | u16::from(palette_addr))];
+        self.frame_buffer[usize::from(self.scanline) * FRAME_WIDTH + x] = color & color_mask;
+    }
+}
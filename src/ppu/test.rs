@@ -0,0 +1,350 @@
+use super::*;
+use crate::mapper::Mapper;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Clone)]
+struct MapperMock {
+    chr: [u8; 0x2000],
+    mirroring: Mirroring,
+}
+
+impl Mapper for MapperMock {
+    fn peek(&self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn cpu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[usize::from(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr[usize::from(addr)] = data
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+fn create_ppu(mirroring: Mirroring) -> Ppu {
+    let mut ppu = Ppu::new();
+    ppu.connect(Rc::new(RefCell::new(Box::new(MapperMock {
+        chr: [0; 0x2000],
+        mirroring,
+    }))));
+    ppu
+}
+
+fn set_vram_addr(ppu: &mut Ppu, addr: u16) {
+    let [lo, hi] = addr.to_le_bytes();
+    ppu.write_register(0x2006, hi);
+    ppu.write_register(0x2006, lo);
+}
+
+#[test]
+fn test_ppudata_write_and_buffered_read() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    set_vram_addr(&mut ppu, 0x2305);
+    ppu.write_register(0x2007, 42);
+    ppu.write_register(0x2007, 24);
+    set_vram_addr(&mut ppu, 0x2305);
+    ppu.read_register(0x2007);
+    assert_eq!(42, ppu.read_register(0x2007));
+    assert_eq!(24, ppu.read_register(0x2007));
+}
+
+#[test]
+fn test_ppudata_increment_32() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.write_register(0x2000, 0b0000_0100);
+    set_vram_addr(&mut ppu, 0x2000);
+    ppu.write_register(0x2007, 1);
+    ppu.write_register(0x2007, 2);
+    assert_eq!(1, ppu.vram[0x0000]);
+    assert_eq!(2, ppu.vram[0x0020]);
+}
+
+#[test]
+fn test_chr_goes_through_mapper() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    set_vram_addr(&mut ppu, 0x1234);
+    ppu.write_register(0x2007, 42);
+    set_vram_addr(&mut ppu, 0x1234);
+    ppu.read_register(0x2007);
+    assert_eq!(42, ppu.read_register(0x2007));
+}
+
+#[test]
+fn test_palette_read_is_not_buffered() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    set_vram_addr(&mut ppu, 0x2F00);
+    ppu.write_register(0x2007, 99);
+    set_vram_addr(&mut ppu, 0x3F10);
+    ppu.write_register(0x2007, 0x2A);
+    set_vram_addr(&mut ppu, 0x3F00);
+    assert_eq!(0x2A, ppu.read_register(0x2007));
+    assert_eq!(99, ppu.read_buffer);
+}
+
+#[test]
+fn test_nametable_mirroring() {
+    let cases = [
+        (Mirroring::Horizontal, [0x000, 0x000, 0x400, 0x400]),
+        (Mirroring::Vertical, [0x000, 0x400, 0x000, 0x400]),
+        (Mirroring::SingleScreenLower, [0x000, 0x000, 0x000, 0x000]),
+        (Mirroring::SingleScreenUpper, [0x400, 0x400, 0x400, 0x400]),
+        (Mirroring::FourScreen, [0x000, 0x400, 0x800, 0xC00]),
+    ];
+    for (mirroring, expected) in cases {
+        let ppu = create_ppu(mirroring);
+        for (table, index) in expected.into_iter().enumerate() {
+            let addr = 0x2000 + 0x400 * table as u16 + 0x10;
+            assert_eq!(index + 0x10, ppu.nametable_index(addr), "{mirroring:?}");
+            assert_eq!(index + 0x10, ppu.nametable_index(addr + 0x1000));
+        }
+    }
+}
+
+#[test]
+fn test_ppuscroll_and_ppuaddr_share_t() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.write_register(0x2000, 0b0000_0011);
+    ppu.write_register(0x2005, 0b0111_1101);
+    assert_eq!(0b101, ppu.x);
+    ppu.write_register(0x2005, 0b0101_1110);
+    assert_eq!(0b0110_1101_0110_1111, ppu.t);
+    ppu.write_register(0x2006, 0b0011_1101);
+    ppu.write_register(0x2006, 0b1111_0000);
+    assert_eq!(0b0011_1101_1111_0000, ppu.v);
+}
+
+#[test]
+fn test_ppustatus_read_resets_latch() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.write_register(0x2006, 0x21);
+    ppu.read_register(0x2002);
+    set_vram_addr(&mut ppu, 0x2400);
+    assert_eq!(0x2400, ppu.v);
+}
+
+#[test]
+fn test_oam_data() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.write_register(0x2003, 0x10);
+    ppu.write_register(0x2004, 42);
+    ppu.write_register(0x2004, 24);
+    ppu.write_register(0x2003, 0x11);
+    assert_eq!(24, ppu.read_register(0x2004));
+    assert_eq!(42, ppu.oam[0x10]);
+}
+
+#[test]
+fn test_vblank_and_nmi() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.tick(u32::from(Timing::Ntsc.vblank_scanline()) * u32::from(DOTS_PER_SCANLINE));
+    assert!(!ppu.status.contains(register::Status::VBLANK));
+    ppu.tick(1);
+    assert!(ppu.status.contains(register::Status::VBLANK));
+    assert!(!ppu.nmi());
+    ppu.write_register(0x2000, 0b1000_0000);
+    assert!(ppu.nmi());
+    assert_eq!(0b1000_0000, ppu.read_register(0x2002) & 0b1110_0000);
+    assert!(!ppu.nmi());
+    assert_eq!(0, ppu.read_register(0x2002) & 0b1000_0000);
+}
+
+#[test]
+fn test_vblank_cleared_on_prerender_scanline() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.tick(261 * u32::from(DOTS_PER_SCANLINE));
+    assert!(ppu.status.contains(register::Status::VBLANK));
+    ppu.tick(1);
+    assert!(!ppu.status.contains(register::Status::VBLANK));
+    ppu.tick(u32::from(DOTS_PER_SCANLINE) - 1);
+    assert_eq!((0, 0, 1), (ppu.scanline(), ppu.dot(), ppu.frame()));
+}
+
+#[test]
+fn test_pal_frame_is_longer() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.set_timing(Timing::Pal);
+    ppu.tick(262 * u32::from(DOTS_PER_SCANLINE));
+    assert_eq!((262, 0), (ppu.scanline(), ppu.frame()));
+}
+
+#[test]
+fn test_vblank_scanline_of_each_timing() {
+    for (timing, vblank_scanline) in [(Timing::Pal, 241u16), (Timing::Dendy, 291)] {
+        let mut ppu = create_ppu(Mirroring::Horizontal);
+        ppu.set_timing(timing);
+        ppu.tick(u32::from(vblank_scanline) * u32::from(DOTS_PER_SCANLINE));
+        assert!(!ppu.is_in_vblank());
+        ppu.tick(1);
+        assert!(ppu.is_in_vblank());
+        assert!(ppu.status.contains(register::Status::VBLANK));
+        ppu.tick(u32::from(311 - vblank_scanline) * u32::from(DOTS_PER_SCANLINE));
+        assert!(!ppu.is_in_vblank());
+    }
+}
+
+fn run_until(ppu: &mut Ppu, frame: u64, scanline: u16) {
+    while (ppu.frame(), ppu.scanline()) != (frame, scanline) {
+        ppu.tick(1);
+    }
+}
+
+/// Tile 1 is fully opaque with color 1, the top-left nametable entry uses it
+fn setup_opaque_tile(ppu: &mut Ppu) {
+    set_vram_addr(ppu, 0x0010);
+    for _ in 0..8 {
+        ppu.write_register(0x2007, 0xFF);
+    }
+    set_vram_addr(ppu, 0x2000);
+    ppu.write_register(0x2007, 1);
+    set_vram_addr(ppu, 0x3F00);
+    ppu.write_register(0x2007, 0x0F);
+    ppu.write_register(0x2007, 0x16);
+    set_vram_addr(ppu, 0x3F11);
+    ppu.write_register(0x2007, 0x2A);
+    set_vram_addr(ppu, 0x0000);
+}
+
+#[test]
+fn test_render_background() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    setup_opaque_tile(&mut ppu);
+    ppu.write_register(0x2001, 0b0000_1010);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    let frame = ppu.frame_buffer();
+    assert_eq!(FRAME_WIDTH * FRAME_HEIGHT, frame.len());
+    assert_eq!(0x16, frame[0]);
+    assert_eq!(0x16, frame[7 * FRAME_WIDTH + 7]);
+    assert_eq!(0x0F, frame[8]);
+    assert_eq!(0x0F, frame[8 * FRAME_WIDTH]);
+}
+
+#[test]
+fn test_fine_scroll() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    setup_opaque_tile(&mut ppu);
+    ppu.write_register(0x2005, 3);
+    ppu.write_register(0x2005, 2);
+    ppu.write_register(0x2001, 0b0000_1010);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    let frame = ppu.frame_buffer();
+    assert_eq!(0x16, frame[4]);
+    assert_eq!(0x0F, frame[5]);
+    assert_eq!(0x16, frame[5 * FRAME_WIDTH]);
+    assert_eq!(0x0F, frame[6 * FRAME_WIDTH]);
+}
+
+#[test]
+fn test_background_left_column_clipping() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    setup_opaque_tile(&mut ppu);
+    ppu.write_register(0x2001, 0b0000_1000);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    assert_eq!(0x0F, ppu.frame_buffer()[0]);
+}
+
+#[test]
+fn test_sprite_zero_hit() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    setup_opaque_tile(&mut ppu);
+    ppu.oam[..4].copy_from_slice(&[0, 1, 0, 4]);
+    ppu.write_register(0x2001, 0b0001_1110);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    assert!(ppu.status.contains(register::Status::SPRITE_ZERO_HIT));
+    // sprites are drawn one line below their Y coordinate
+    let frame = ppu.frame_buffer();
+    assert_eq!(0x16, frame[4]);
+    assert_eq!(0x16, frame[FRAME_WIDTH + 3]);
+    assert_eq!(0x2A, frame[FRAME_WIDTH + 4]);
+}
+
+#[test]
+fn test_sprite_behind_background() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    setup_opaque_tile(&mut ppu);
+    ppu.oam[..4].copy_from_slice(&[0, 1, 0b0010_0000, 4]);
+    ppu.write_register(0x2001, 0b0001_1110);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    let frame = ppu.frame_buffer();
+    assert_eq!(0x16, frame[FRAME_WIDTH + 4]);
+    assert_eq!(0x2A, frame[FRAME_WIDTH + 8]);
+}
+
+#[test]
+fn test_no_sprite_zero_hit_on_transparent_background() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    setup_opaque_tile(&mut ppu);
+    ppu.oam[..4].copy_from_slice(&[0, 1, 0, 100]);
+    ppu.write_register(0x2001, 0b0001_1110);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    assert!(!ppu.status.contains(register::Status::SPRITE_ZERO_HIT));
+    assert_eq!(0x2A, ppu.frame_buffer()[FRAME_WIDTH + 100]);
+}
+
+#[test]
+fn test_sprite_overflow() {
+    for (sprites, expected) in [(8, false), (9, true)] {
+        let mut ppu = create_ppu(Mirroring::Horizontal);
+        ppu.oam.fill(0xFF);
+        for sprite in 0..sprites {
+            ppu.oam[sprite * 4..sprite * 4 + 4].copy_from_slice(&[50, 1, 0, 0]);
+        }
+        ppu.write_register(0x2001, 0b0001_0000);
+        run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+        let overflow = ppu.status.contains(register::Status::SPRITE_OVERFLOW);
+        assert_eq!(expected, overflow, "{sprites} sprites");
+    }
+}
+
+#[test]
+fn test_sprite_overflow_hardware_bug() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.oam.fill(0xFF);
+    for sprite in 0..8 {
+        ppu.oam[sprite * 4..sprite * 4 + 4].copy_from_slice(&[50, 1, 0, 0]);
+    }
+    // only the tile number of the 10th sprite is in range, but it is read as Y
+    ppu.oam[36..40].copy_from_slice(&[0xFF, 50, 0xFF, 0xFF]);
+    ppu.write_register(0x2001, 0b0001_0000);
+    run_until(&mut ppu, 0, 51);
+    assert!(ppu.status.contains(register::Status::SPRITE_OVERFLOW));
+    ppu.oam[36..40].fill(0xFF);
+    run_until(&mut ppu, 1, Timing::Ntsc.vblank_scanline());
+    assert!(!ppu.status.contains(register::Status::SPRITE_OVERFLOW));
+}
+
+#[test]
+fn test_odd_frame_skips_a_dot_when_rendering() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    ppu.write_register(0x2001, 0b0000_1000);
+    let frame_length = |ppu: &mut Ppu, frame| {
+        let start = ppu.cycle;
+        run_until(ppu, frame, 0);
+        ppu.cycle - start
+    };
+    assert_eq!(262 * 341, frame_length(&mut ppu, 1));
+    assert_eq!(262 * 341 - 1, frame_length(&mut ppu, 2));
+    ppu.write_register(0x2001, 0);
+    assert_eq!(262 * 341, frame_length(&mut ppu, 3));
+    assert_eq!(262 * 341, frame_length(&mut ppu, 4));
+}
+
+#[test]
+fn test_peek_register_has_no_side_effects() {
+    let mut ppu = create_ppu(Mirroring::Horizontal);
+    set_vram_addr(&mut ppu, 0x2305);
+    ppu.write_register(0x2007, 42);
+    set_vram_addr(&mut ppu, 0x2305);
+    ppu.read_register(0x2007);
+    assert_eq!(42, ppu.peek_register(0x2007));
+    assert_eq!(42, ppu.peek_register(0x2007));
+    assert_eq!(42, ppu.read_register(0x2007));
+    assert_eq!(0x2307, ppu.v);
+}
@@ -0,0 +1,268 @@
+//! Instruction traces : a line per executed instruction, with the state of
+//! the CPU before it runs and the position of the PPU.
+use crate::cpu::{disassemble, Registers};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// State before an instruction is executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub registers: Registers,
+    /// Opcode and the two following bytes
+    pub bytes: [u8; 3],
+    pub cycles: u64,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
+impl TraceEntry {
+    /// Disassembly of the instruction and its length
+    pub fn disassemble(&self) -> (String, u8) {
+        let pc = self.registers.pc;
+        disassemble(|addr| self.bytes[usize::from(addr.wrapping_sub(pc))], pc)
+    }
+
+    pub fn format(&self, format: TraceFormat) -> String {
+        let (disassembly, len) = self.disassemble();
+        let bytes = self.bytes[..usize::from(len)]
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let Registers { pc, a, x, y, sp, p } = self.registers;
+        match format {
+            TraceFormat::Nintendulator => format!(
+                "{pc:04X}  {bytes:<8}  {disassembly:<31} A:{a:02X} X:{x:02X} Y:{y:02X} P:{p:02X} SP:{sp:02X} PPU:{:>3},{:>3} CYC:{}",
+                self.scanline, self.dot, self.cycles
+            ),
+            TraceFormat::Flags => format!(
+                "{pc:04X} {bytes:<8} {disassembly:<14} A:{a:02X} X:{x:02X} Y:{y:02X} SP:{sp:02X} {} CYC:{} SL:{} DOT:{}",
+                flags(p),
+                self.cycles,
+                self.scanline,
+                self.dot
+            ),
+        }
+    }
+}
+
+/// Status as `NV-BDIZC`, in lowercase when clear
+fn flags(status: u8) -> String {
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(bit, flag)| {
+            if status & 0x80 >> bit != 0 {
+                flag
+            } else {
+                flag.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The layout of the nestest logs, without the values at the effective
+    /// address
+    Nintendulator,
+    /// Status as letters, uppercase when set
+    Flags,
+}
+
+pub enum TraceSink {
+    Writer(Box<dyn Write>),
+    /// Keeps the last `capacity` lines
+    RingBuffer {
+        lines: VecDeque<String>,
+        capacity: usize,
+    },
+}
+
+type Trigger = Box<dyn FnMut(&TraceEntry) -> bool>;
+
+/// Formats and stores a line per instruction, once its trigger fired and
+/// while the PC is in its range.
+pub struct Tracer {
+    sink: TraceSink,
+    format: TraceFormat,
+    range: Option<Range<u16>>,
+    trigger: Option<Trigger>,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(sink: TraceSink) -> Self {
+        Self {
+            sink,
+            format: TraceFormat::Nintendulator,
+            range: None,
+            trigger: None,
+            error: None,
+        }
+    }
+
+    /// Writes the lines to a file or any other writer.
+    pub fn to_writer(writer: impl Write + 'static) -> Self {
+        Self::new(TraceSink::Writer(Box::new(writer)))
+    }
+
+    pub fn ring_buffer(capacity: usize) -> Self {
+        Self::new(TraceSink::RingBuffer {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    pub fn with_format(mut self, format: TraceFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Only traces instructions whose address is in `range`.
+    pub fn with_range(mut self, range: Range<u16>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Starts tracing from the first instruction for which `trigger` is true.
+    pub fn start_when(mut self, trigger: impl FnMut(&TraceEntry) -> bool + 'static) -> Self {
+        self.trigger = Some(Box::new(trigger));
+        self
+    }
+
+    /// Lines kept by a ring buffer, oldest first.
+    pub fn lines(&self) -> impl Iterator<Item = &String> {
+        let lines = match &self.sink {
+            TraceSink::RingBuffer { lines, .. } => Some(lines.iter()),
+            TraceSink::Writer(_) => None,
+        };
+        lines.into_iter().flatten()
+    }
+
+    /// First error of the writer, nothing is written after it.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) {
+        if let Some(trigger) = &mut self.trigger {
+            if !trigger(entry) {
+                return;
+            }
+            self.trigger = None;
+        }
+        if let Some(range) = &self.range {
+            if !range.contains(&entry.registers.pc) {
+                return;
+            }
+        }
+        let line = entry.format(self.format);
+        match &mut self.sink {
+            TraceSink::Writer(writer) => {
+                if self.error.is_none() {
+                    self.error = writeln!(writer, "{line}").err();
+                }
+            }
+            TraceSink::RingBuffer { lines, capacity } => {
+                if lines.len() == *capacity {
+                    lines.pop_front();
+                }
+                if *capacity > 0 {
+                    lines.push_back(line);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_entry(pc: u16) -> TraceEntry {
+        TraceEntry {
+            registers: Registers {
+                pc,
+                a: 0,
+                x: 0,
+                y: 0,
+                sp: 0xFD,
+                p: 0x24,
+            },
+            bytes: [0x4C, 0xF5, 0xC5],
+            cycles: 7,
+            scanline: 0,
+            dot: 21,
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let entry = create_entry(0xC000);
+        assert_eq!(
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+            entry.format(TraceFormat::Nintendulator)
+        );
+        assert_eq!(
+            "C000 4C F5 C5 JMP $C5F5      A:00 X:00 Y:00 SP:FD nv-bdIzc CYC:7 SL:0 DOT:21",
+            entry.format(TraceFormat::Flags)
+        );
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut tracer = Tracer::ring_buffer(2);
+        for pc in 0..3 {
+            tracer.record(&create_entry(pc));
+        }
+        let lines: Vec<&String> = tracer.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("0001"));
+        assert!(lines[1].starts_with("0002"));
+    }
+
+    /// Accepts `capacity` bytes then fails
+    struct LimitedWriter {
+        capacity: usize,
+    }
+
+    impl Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.capacity == 0 {
+                return Err(io::Error::new(io::ErrorKind::StorageFull, "full"));
+            }
+            let len = buf.len().min(self.capacity);
+            self.capacity -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer_error() {
+        let mut tracer = Tracer::to_writer(LimitedWriter { capacity: 100 });
+        tracer.record(&create_entry(0));
+        assert!(tracer.error().is_none());
+        tracer.record(&create_entry(1));
+        assert_eq!(io::ErrorKind::StorageFull, tracer.error().unwrap().kind());
+        tracer.record(&create_entry(2));
+        assert_eq!(io::ErrorKind::StorageFull, tracer.error().unwrap().kind());
+    }
+
+    #[test]
+    fn test_range_and_trigger() {
+        let mut tracer = Tracer::ring_buffer(8)
+            .with_range(0x8000..0x9000)
+            .start_when(|entry| entry.registers.pc == 0x8002);
+        for pc in [0x8000, 0x8002, 0x9000, 0x8000] {
+            tracer.record(&create_entry(pc));
+        }
+        let pcs: Vec<&str> = tracer.lines().map(|line| &line[..4]).collect();
+        assert_eq!(vec!["8002", "8000"], pcs);
+    }
+}
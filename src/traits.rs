@@ -1,35 +1,136 @@
-pub trait Device {
+/// Chip whose registers are mapped in the CPU address space, owned by the
+/// bus which routes the accesses in `mapping_def` to it.
+pub trait Device: DeviceClone {
     fn mapping_def(&self) -> std::ops::Range<usize>;
 
-    fn map(&mut self, memory: &mut [u8]);
+    /// Read by the CPU, with the side effects it has on the chip.
+    fn read(&mut self, addr: u16) -> u8;
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read(&mut self);
+    fn write(&mut self, addr: u16, value: u8);
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write(&mut self);
+    /// The value `read` would return, without its side effects, for
+    /// debuggers and trace loggers.
+    fn peek(&self, addr: u16) -> u8;
+
+    /// Data lines driven when reading `addr`, the others keep the last value
+    /// on the CPU data bus.
+    fn driven_bits(&self, _addr: u16) -> u8 {
+        0xFF
+    }
+}
+
+/// Lets a bus holding boxed devices be cloned, for save states.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<T: Device + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
 pub trait Memory {
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn load(&mut self, data: &[u8], address: u16);
+    fn load(&mut self, data: &[u8], address: u16);
+
+    fn mem_read_u8(&mut self, address: u16) -> u8;
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read_u8(&mut self, address: u16) -> u8;
+    fn mem_write_u8(&mut self, address: u16, byte: u8);
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write_u8(&mut self, address: u16, byte: u8);
+    /// The value `mem_read_u8` would return, without its side effects, for
+    /// debuggers and trace loggers.
+    fn peek(&self, address: u16) -> u8;
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_read_u16(&mut self, address: u16) -> u16 {
-        let bytes = [self.mem_read_u8(address), self.mem_read_u8(address + 1)];
+    fn mem_read_u16(&mut self, address: u16) -> u16 {
+        let bytes = [
+            self.mem_read_u8(address),
+            self.mem_read_u8(address.wrapping_add(1)),
+        ];
         u16::from_le_bytes(bytes)
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn mem_write_u16(&mut self, address: u16, word: u16) {
+    fn mem_write_u16(&mut self, address: u16, word: u16) {
         let [lo, hi] = word.to_le_bytes();
         self.mem_write_u8(address, lo);
-        self.mem_write_u8(address + 1, hi)
+        self.mem_write_u8(address.wrapping_add(1), hi)
+    }
+
+    /// State of the /IRQ line, the CPU samples it between instructions.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// State of the /NMI line, sampled on every cycle by a cycle-accurate CPU.
+    fn nmi(&self) -> bool {
+        false
+    }
+
+    /// Called by a cycle-accurate CPU at the start of each cycle, before its
+    /// bus access, to clock the other chips.
+    fn tick(&mut self) {}
+
+    /// Cycles the CPU was halted for by DMAs since the last call, it adds
+    /// them to its count.
+    fn take_stall_cycles(&mut self) -> u32 {
+        0
+    }
+
+    /// Scanline and dot of the PPU for the trace lines, 0 on a bus without
+    /// one.
+    fn ppu_position(&self) -> (u16, u16) {
+        (0, 0)
+    }
+}
+
+/// A CPU can also borrow its memory instead of owning it.
+impl<M: Memory + ?Sized> Memory for &mut M {
+    fn load(&mut self, data: &[u8], address: u16) {
+        (**self).load(data, address)
+    }
+
+    fn mem_read_u8(&mut self, address: u16) -> u8 {
+        (**self).mem_read_u8(address)
+    }
+
+    fn mem_write_u8(&mut self, address: u16, byte: u8) {
+        (**self).mem_write_u8(address, byte)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        (**self).peek(address)
+    }
+
+    fn mem_read_u16(&mut self, address: u16) -> u16 {
+        (**self).mem_read_u16(address)
+    }
+
+    fn mem_write_u16(&mut self, address: u16, word: u16) {
+        (**self).mem_write_u16(address, word)
+    }
+
+    fn irq(&self) -> bool {
+        (**self).irq()
+    }
+
+    fn nmi(&self) -> bool {
+        (**self).nmi()
+    }
+
+    fn tick(&mut self) {
+        (**self).tick()
+    }
+
+    fn take_stall_cycles(&mut self) -> u32 {
+        (**self).take_stall_cycles()
+    }
+
+    fn ppu_position(&self) -> (u16, u16) {
+        (**self).ppu_position()
     }
 }